@@ -13,7 +13,6 @@ fn main() {
     let runners_dir = Path::new(&out_dir).join("runners");
 
     let mut lang_macro = fs::File::create(Path::new(&out_dir).join("lang_macro")).unwrap();
-    let mut lang_list = fs::File::create(Path::new(&out_dir).join("lang_list")).unwrap();
     let mut snippet_map = fs::File::create(Path::new(&out_dir).join("snippet_map")).unwrap();
     let mut runners_list = fs::File::create(Path::new(&out_dir).join("runners_list")).unwrap();
     let mut alias_map = fs::File::create(Path::new(&out_dir).join("alias_map")).unwrap();
@@ -22,9 +21,6 @@ fn main() {
     fs::create_dir_all(&runners_dir).unwrap();
 
     lang_macro.write_all(b"macro_rules! LANGS {\n").unwrap();
-    lang_list
-        .write_all(b"const LANGS: [&str; LANGS!(len)] = [\n")
-        .unwrap();
     snippet_map.write_all(b"HashMap::from([\n").unwrap();
 
     let snippets = fs::read_dir("src/snippets/")
@@ -47,8 +43,6 @@ fn main() {
         )
         .unwrap();
 
-        writeln!(lang_list, "LANGS![{i}], ").unwrap();
-
         writeln!(
             snippet_map,
             "(LANGS![{i}], include_str!(concat!(\"snippets/\", LANGS![{i}]))),"
@@ -59,11 +53,9 @@ fn main() {
     writeln!(lang_macro, "(len) => {{ {} }}", snippets.len()).unwrap();
     lang_macro.write_all(b"}\n").unwrap();
 
-    lang_list.write_all(b"];\n").unwrap();
-
     snippet_map.write_all(b"])\n").unwrap();
 
-    runners_list.write_all(b"&[\n").unwrap();
+    runners_list.write_all(b"vec![\n").unwrap();
 
     let runners = fs::read_dir("src/runners/")
         .unwrap()
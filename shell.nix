@@ -55,6 +55,7 @@ pkgs.mkShell {
         [ -n "$RUC_EDITOR" ] && {
             echo Using "$RUC_EDITOR" as the editor
             export EDITOR="$RUC_EDITOR"
+            export VISUAL="$RUC_EDITOR"
         }
         PATH="$PWD/scripts/:$PATH"
     '';
@@ -1,16 +1,33 @@
 use std::collections::HashMap;
-use std::convert::identity;
 use std::env;
+use std::ffi::CStr;
+use std::ffi::CString;
+use std::ffi::OsString;
 use std::fs;
 use std::io;
+use std::io::BufRead;
+use std::io::IsTerminal;
+use std::io::Read;
 use std::io::Write;
+use std::mem;
+use std::os::fd::AsFd;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::process::ExitStatusExt;
+use std::path;
 use std::path::Path;
 use std::path::PathBuf;
 use std::process;
 use std::process::Command;
 use std::process::Stdio;
+use std::ptr;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
 
-#[derive(PartialEq, Copy, Clone)]
+#[derive(PartialEq, Copy, Clone, Debug)]
 enum Codes {
     InternalError = -1,
     Ok = 0,
@@ -21,6 +38,9 @@ enum Codes {
     EditorError = 5,
     FileError = 6,
     CodeError = 7,
+    Interrupted = 8,
+    LimitError = 9,
+    SandboxError = 10,
 }
 
 type Error<T> = Result<T, Codes>;
@@ -68,6 +88,12 @@ enum Hist {
     New,
 }
 
+#[derive(PartialEq, Clone, Copy)]
+enum Format {
+    Human,
+    Json,
+}
+
 #[derive(PartialEq, Clone, Copy)]
 enum List {
     None,
@@ -78,60 +104,131 @@ enum List {
 
 struct Args {
     hist: Hist,
+    rerun: bool,
+    watch: bool,
+    interactive: bool,
+    exit_status: bool,
+    warnings: bool,
+    rebuild: bool,
+    time: bool,
+    format: Format,
+    limits: limits::Limits,
+    limit_setup: bool,
+    sandbox: bool,
+    sandbox_setup: bool,
     list: List,
     editor: String,
     cache_dir: PathBuf,
+    config_dir: PathBuf,
     compiler_args: Vec<String>,
     prog_args: Vec<String>,
     runner: Option<String>,
     lang: String,
+    file: Option<PathBuf>,
+    prog_stdin: Option<PathBuf>,
 }
 
 fn parse_args() -> Args {
     fn help() -> ! {
         die!(
             Codes::Ok,
-            r#"usage: {} LANG [OPTIONS]
+            r#"usage: {0} LANG [OPTIONS] [-- PROG_ARGS...]
+       {0} FILE [OPTIONS] [-- PROG_ARGS...]
 
     Open the EDITOR. Write some code. Have it executed.
 
     positional arguments:
     LANG                    language to be ran
+    FILE                    file to be ran instead of opening the editor. the
+                            language is inferred from the file extension
 
     options:
 
     -r, --runner RUNNER     select which runner to use
 
-    -e, --editor EDITOR     specify name of the editor to use. by default uses
-                            the EDITOR environment variable
+    -e, --editor EDITOR     specify the editor command to use, it may include
+                            arguments (e.g. "code --wait"). by default uses the
+                            VISUAL or the EDITOR environment variable
+
+    -f, --file FILE         run FILE instead of opening the editor. history is
+                            not used
 
     -t, --temp              ignore history and use default snippet
     -n, --new-history       reset current language history to default
     -u, --use-histoty       use the history file (default)
+        --rerun, --no-edit  run the history file without opening the editor
+    -w, --watch             start the editor in the background and run the code
+                            every time the file is saved. useful with graphical
+                            editors
+    -i, --interactive       after running the code, offer to edit it again,
+                            rerun it or change the arguments
+    -x, --exit-status       exit with the exit status of the executed program
+                            (128 + the signal number if it was killed by a
+                            signal) instead of 0
+    -W, --warnings          show the standard error of setup and teardown
+                            commands (e.g. compiler warnings) as it is produced,
+                            even if they succeed
+    -B, --rebuild           run setup even if the compiled program is cached
+        --time              after running the code, show how long each setup
+                            command, the program and each teardown command
+                            took, their CPU time and peak memory usage
+        --format FORMAT     how to report the result. 'human' (default) or
+                            'json', which writes a single JSON document with
+                            the result of every command to stdout (all other
+                            messages go to stderr)
 
         --cache-dir DIR     which directory to use for caches. by default
                             $XDG_CACHE_HOME or $HOME/.cache. the directory will
                             be created if it does not exist
+        --config-dir DIR    which directory to read user configuration from. by
+                            default $XDG_CONFIG_HOME or $HOME/.config
 
     -l, --ls                list available languages
     -a, --aliases           list available aliases
         --list-runners      list available runners. if a language is specified,
                             only runners for that language are listed
 
-    --args ARGS             space separated list of arguments to be passed to
-                            the compiler or the interpreter.
-    --argv ARGS             space separated list of arguments to be passed to
-                            the executed program
+    --args ARGS             list of arguments to be passed to the compiler or
+                            the interpreter. split using shell quoting rules
+    --argv ARGS             list of arguments to be passed to the executed
+                            program. split using shell quoting rules
+    --                      pass all the following arguments to the executed
+                            program verbatim (after the ones from --argv)
+
+        --stdin FILE        use FILE as the standard input of the executed
+                            program
+
+        --timeout SECS      kill the executed program (and everything it
+                            started) after SECS seconds
+        --memory-limit SIZE limit the address space of the executed program to
+                            SIZE bytes. K, M and G suffixes are accepted
+        --cpu-limit SECS    limit the CPU time of the executed program
+        --max-procs N       limit the number of processes the user may have
+                            while the program runs (RLIMIT_NPROC)
+        --limit-setup       also apply the limits to setup commands
+
+        --sandbox           run the executed program in new user, mount,
+                            network and PID namespaces, with a read-only view
+                            of the filesystem (except for a private scratch
+                            directory) and no network
+        --sandbox-setup     also run setup commands in the sandbox
 
     -h, --help              show this help message and exit
     -v, --version           print program version
 
 
     Notes:
+        If the standard input is a pipe or a file, the code is read from it
+        instead of opening the editor. History is not used in this case
+
         Between -t, -n and -u, the last option specified will be used
 
         Between -l, -a and --list-runners, the last option specified will be used
 
+        A program which exits with a non-zero status is not an error, unless
+        --exit-status is used. A failing setup (e.g. a compilation error) is
+        always reported as a code error
+
     Exit codes:
         -1: Internal error
          0: OK
@@ -142,6 +239,9 @@ fn parse_args() -> Args {
          5: Editor error
          6: File error
          7: Code error
+         8: Interrupted by a signal
+         9: Limit error (see --timeout and --cpu-limit)
+        10: Sandbox error
     "#,
             Path::new(&env::args().next().unwrap())
                 .file_name()
@@ -150,14 +250,31 @@ fn parse_args() -> Args {
         )
     }
     let mut hist = Hist::Use;
+    let mut rerun = false;
+    let mut watch = false;
+    let mut interactive = false;
+    let mut exit_status = false;
+    let mut warnings = false;
+    let mut rebuild = false;
+    let mut time = false;
+    let mut format = Format::Human;
+    let mut limits = limits::Limits::default();
+    let mut limit_setup = false;
+    let mut sandbox = false;
+    let mut sandbox_setup = false;
     let mut list = List::None;
     let mut editor = String::new();
     let mut compiler_args = Vec::new();
     let mut prog_args = Vec::new();
+    let mut trailing_args = Vec::new();
     let mut runner = None;
     let mut lang = String::new();
+    let mut file = None;
+    let mut prog_stdin = None;
     let mut cache_dir = dirs::cache_dir()
         .unwrap_or_else(|| die!(Codes::InternalError, "Could not locate cache directory"));
+    let mut config_dir = dirs::config_dir()
+        .unwrap_or_else(|| die!(Codes::InternalError, "Could not locate config directory"));
 
     let mut args = env::args();
     args.next();
@@ -166,6 +283,57 @@ fn parse_args() -> Args {
             "-t" | "--temp" => hist = Hist::Temp,
             "-n" | "--new-history" => hist = Hist::New,
             "-u" | "--use-history" => hist = Hist::Use,
+            "--rerun" | "--no-edit" => rerun = true,
+            "-w" | "--watch" => watch = true,
+            "-i" | "--interactive" => interactive = true,
+            "-x" | "--exit-status" => exit_status = true,
+            "-W" | "--warnings" => warnings = true,
+            "-B" | "--rebuild" => rebuild = true,
+            "--time" => time = true,
+            "--limit-setup" => limit_setup = true,
+            "--sandbox" => sandbox = true,
+            "--sandbox-setup" => sandbox_setup = true,
+            flag @ ("--timeout" | "--memory-limit" | "--cpu-limit" | "--max-procs") => {
+                let value = args.next().unwrap_or_else(|| {
+                    die!(Codes::ArgumentError, "Expected a value after '{flag}'")
+                });
+                let invalid =
+                    || -> ! { die!(Codes::ArgumentError, "Invalid value '{value}' for '{flag}'") };
+                match flag {
+                    "--timeout" => {
+                        limits.timeout = Some(
+                            value
+                                .parse()
+                                .ok()
+                                .filter(|secs: &f64| secs.is_finite() && *secs > 0.0)
+                                .map(Duration::from_secs_f64)
+                                .unwrap_or_else(|| invalid()),
+                        )
+                    }
+                    "--memory-limit" => {
+                        limits.memory =
+                            Some(limits::parse_size(&value).unwrap_or_else(|| invalid()))
+                    }
+                    "--cpu-limit" => {
+                        limits.cpu = Some(
+                            value
+                                .parse()
+                                .ok()
+                                .filter(|&secs| secs > 0)
+                                .unwrap_or_else(|| invalid()),
+                        )
+                    }
+                    _ => {
+                        limits.procs = Some(
+                            value
+                                .parse()
+                                .ok()
+                                .filter(|&n| n > 0)
+                                .unwrap_or_else(|| invalid()),
+                        )
+                    }
+                }
+            }
             "-l" | "--ls" => list = List::Langs,
             "-a" | "--aliases" => list = List::Aliases,
             "--list-runners" => list = List::Runners,
@@ -174,18 +342,20 @@ fn parse_args() -> Args {
                 exit(Codes::Ok);
             }
             "-h" | "--help" => help(),
+            "--" => trailing_args.extend(args.by_ref()),
             "--args" | "--argv" => {
-                let split = args
-                    .next()
-                    .unwrap_or_else(|| {
-                        die!(
-                            Codes::ArgumentError,
-                            "Expected a list of space separated arguments after '{arg}'"
-                        )
-                    })
-                    .split(' ')
-                    .map(str::to_string)
-                    .collect();
+                let split = shell::split(&args.next().unwrap_or_else(|| {
+                    die!(
+                        Codes::ArgumentError,
+                        "Expected a list of arguments after '{arg}'"
+                    )
+                }))
+                .unwrap_or_else(|e| {
+                    die!(
+                        Codes::ArgumentError,
+                        "Could not parse arguments after '{arg}': {e}"
+                    )
+                });
                 if arg == "--args" {
                     compiler_args = split;
                 } else {
@@ -205,6 +375,32 @@ fn parse_args() -> Args {
                     die!(Codes::ArgumentError, "Expected editor name after '{flag}'")
                 });
             }
+            flag @ "-f" | flag @ "--file" => {
+                file = Some(
+                    args.next()
+                        .unwrap_or_else(|| {
+                            die!(Codes::ArgumentError, "Expected file path after '{flag}'")
+                        })
+                        .into(),
+                );
+            }
+            "--format" => {
+                format = match args.next().as_deref() {
+                    Some("human") => Format::Human,
+                    Some("json") => Format::Json,
+                    Some(other) => die!(Codes::ArgumentError, "Unknown format '{other}'"),
+                    None => die!(Codes::ArgumentError, "Expected a format after --format"),
+                }
+            }
+            "--stdin" => {
+                prog_stdin = Some(
+                    args.next()
+                        .unwrap_or_else(|| {
+                            die!(Codes::ArgumentError, "Expected file path after --stdin")
+                        })
+                        .into(),
+                );
+            }
             "--cache-dir" => {
                 cache_dir = args
                     .next()
@@ -216,6 +412,17 @@ fn parse_args() -> Args {
                     })
                     .into();
             }
+            "--config-dir" => {
+                config_dir = args
+                    .next()
+                    .unwrap_or_else(|| {
+                        die!(
+                            Codes::ArgumentError,
+                            "Expected directory path after --config-dir"
+                        )
+                    })
+                    .into();
+            }
             _ => {
                 if lang.is_empty() {
                     if arg.starts_with('-') {
@@ -228,28 +435,82 @@ fn parse_args() -> Args {
             }
         }
     }
-    if lang.is_empty() && list == List::None {
+    prog_args.extend(trailing_args);
+    if lang.is_empty() && list == List::None && file.is_none() {
         die!(Codes::ArgumentError, "Expected exactly one language")
     }
+    if rerun && hist != Hist::Use {
+        die!(
+            Codes::ArgumentError,
+            "--rerun cannot be used together with --temp or --new-history"
+        )
+    }
+    if watch && (rerun || file.is_some()) {
+        die!(
+            Codes::ArgumentError,
+            "--watch cannot be used together with --rerun or --file"
+        )
+    }
+    if interactive && (watch || file.is_some()) {
+        die!(
+            Codes::ArgumentError,
+            "--interactive cannot be used together with --watch or --file"
+        )
+    }
+    if exit_status && watch {
+        die!(
+            Codes::ArgumentError,
+            "--exit-status cannot be used together with --watch"
+        )
+    }
+    if format == Format::Json && (watch || interactive) {
+        die!(
+            Codes::ArgumentError,
+            "--format json cannot be used together with --watch or --interactive"
+        )
+    }
 
     return Args {
         hist,
+        rerun,
+        watch,
+        interactive,
+        exit_status,
+        warnings,
+        rebuild,
+        time,
+        format,
+        limits,
+        limit_setup,
+        sandbox,
+        sandbox_setup,
         list,
         cache_dir,
+        config_dir,
         editor,
         compiler_args,
         prog_args,
         runner,
         lang,
+        file,
+        prog_stdin,
     };
 }
 
-fn list(kind: List, lang: &str, aliases: &Aliases, runners: &Runners) -> Error<()> {
+fn list(
+    kind: List,
+    lang: &str,
+    snippets: &Snippets,
+    aliases: &Aliases,
+    runners: &Runners,
+) -> Error<()> {
     match kind {
         List::None => return Ok(()),
         List::Langs => {
             println!("Avaliable languages:\n{}", HLINE);
-            for lang in LANGS {
+            let mut langs = snippets.keys().collect::<Vec<_>>();
+            langs.sort();
+            for lang in langs {
                 println!("    {lang}");
             }
         }
@@ -266,7 +527,7 @@ fn list(kind: List, lang: &str, aliases: &Aliases, runners: &Runners) -> Error<(
             }
             println!(":\n{}", HLINE);
             if lang.is_empty() {
-                for runner in runners.runners {
+                for runner in &runners.runners {
                     print!("    {} : ", runner.name);
                     let mut it = runner.supported_langs.iter();
                     print!(
@@ -282,7 +543,7 @@ fn list(kind: List, lang: &str, aliases: &Aliases, runners: &Runners) -> Error<(
                     println!("");
                 }
             } else {
-                for runner in runners.runners {
+                for runner in &runners.runners {
                     if runner.supported_langs.contains(&lang) {
                         println!("{}", runner.name);
                     }
@@ -303,51 +564,17 @@ fn output_printer(v: &Vec<u8>, indent: usize) {
 }
 
 fn check_status(exe: &[&str], res: &process::Output, code: Codes) -> Error<()> {
-    check_status_impl(exe, res, code, None)
-}
-
-fn check_status_template(
-    exe: &[&str],
-    res: &process::Output,
-    code: Codes,
-    conf: &template::Conf,
-) -> Error<()> {
-    check_status_impl(exe, res, code, Some(conf))
-}
-
-fn check_status_impl(
-    exe: &[&str],
-    res: &process::Output,
-    code: Codes,
-    conf: Option<&template::Conf>,
-) -> Error<()> {
     if res.status.success() {
         Ok(())
     } else {
-        let mut out_name = String::new();
-
         let exe = exe
             .iter()
-            .map(|s| {
-                if let Some(conf) = conf {
-                    template::sub(s, conf, &mut out_name)
-                } else {
-                    Ok(s.to_string())
-                }
-            })
-            .fold(Ok(String::new()), |acc, s| {
-                if let Ok(s) = s {
-                    if s.is_empty() {
-                        acc
-                    } else {
-                        Ok(acc? + &s + " ")
-                    }
-                } else {
-                    s
-                }
-            })?;
+            .filter(|s| !s.is_empty())
+            .fold(String::new(), |acc, s| acc + s + " ");
         if let Some(code) = res.status.code() {
             println!("{exe} exited with '{code}'");
+        } else if let Some(sig) = res.status.signal() {
+            println!("{exe} was killed by {}", signals::name(sig));
         } else {
             println!("{exe} was closed by a signal");
         }
@@ -363,115 +590,205 @@ fn check_status_impl(
     }
 }
 
-fn find_exe<'a>(dep: &'a str) -> Option<&'a str> {
+// NOTE: FNV-1a, unlike `DefaultHasher` it does not change between builds of ruc
+struct StableHasher(u64);
+
+impl StableHasher {
+    fn new() -> Self {
+        StableHasher(0xcbf29ce484222325)
+    }
+
+    // NOTE: Length-prefixed, so that ["ab", "c"] and ["a", "bc"] differ
+    fn add(self: &mut Self, bytes: &[u8]) {
+        for byte in (bytes.len() as u64).to_le_bytes().iter().chain(bytes) {
+            self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+fn find_exe_path(dep: &str) -> Option<PathBuf> {
     // https://stackoverflow.com/a/37499032
-    if env::var_os("PATH")
-        .and_then(|paths| {
-            env::split_paths(&paths).find_map(|dir| {
-                let full_path = dir.join(&dep);
-                if full_path.is_file() {
-                    Some(full_path)
-                } else {
-                    None
-                }
-            })
+    env::var_os("PATH").and_then(|paths| {
+        env::split_paths(&paths).find_map(|dir| {
+            let full_path = dir.join(&dep);
+            if full_path.is_file() {
+                Some(full_path)
+            } else {
+                None
+            }
         })
-        .is_some()
-    {
-        Some(dep)
-    } else {
-        None
-    }
+    })
+}
+
+fn find_exe<'a>(dep: &'a str) -> Option<&'a str> {
+    find_exe_path(dep).map(|_| dep)
 }
 
 mod template {
     use super::*;
 
-    pub(crate) struct Rep<'a> {
-        pub string: &'a str,
-        pub is_out: bool,
+    pub(crate) const INPUT_FILE: &str = "INPUT_FILE";
+    pub(crate) const OUTPUT_FILE: &str = "OUTPUT_FILE";
+    pub(crate) const COMPILER_ARGS: &str = "COMPILER_ARGS";
+    pub(crate) const PROG_ARGS: &str = "PROG_ARGS";
+    pub(crate) const EXE: &str = "EXE";
+    pub(crate) const INPUT_DIR: &str = "INPUT_DIR";
+    pub(crate) const INPUT_STEM: &str = "INPUT_STEM";
+    pub(crate) const WORK_DIR: &str = "WORK_DIR";
+    pub(crate) const CACHE_DIR: &str = "CACHE_DIR";
+
+    pub(crate) enum Value<'a> {
+        Str(&'a str),
+        List(&'a [String]),
+    }
+
+    pub(crate) type Conf<'a> = HashMap<&'a str, Value<'a>>;
+
+    enum Token<'a> {
+        Text(&'a str),
+        Var { name: &'a str, suffix: &'a str },
     }
-    impl<'a> Rep<'a> {
-        pub(crate) fn new(s: &'a str) -> Self {
-            Rep {
-                string: s,
-                is_out: false,
+
+    // NOTE: `%NAME%` or `%NAME:SUFFIX%`, `%%` is a literal `%`
+    fn tokenize(inp: &str) -> Result<Vec<Token<'_>>, String> {
+        let mut tokens = Vec::new();
+        let mut rest = inp;
+        while let Some(start) = rest.find('%') {
+            if start > 0 {
+                tokens.push(Token::Text(&rest[..start]));
             }
-        }
-        pub(crate) fn out(s: &'a str) -> Self {
-            Rep {
-                string: s,
-                is_out: true,
+            rest = &rest[start + 1..];
+            if let Some(stripped) = rest.strip_prefix('%') {
+                tokens.push(Token::Text("%"));
+                rest = stripped;
+                continue;
+            }
+            let end = rest.find('%').ok_or_else(|| {
+                format!("unterminated template string in '{inp}' (use '%%' for a literal '%')")
+            })?;
+            let (name, suffix) = rest[..end].split_once(':').unwrap_or((&rest[..end], ""));
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+            {
+                return Err(format!(
+                    "invalid template string '%{}%' in '{inp}' (use '%%' for a literal '%')",
+                    &rest[..end]
+                ));
             }
+            tokens.push(Token::Var { name, suffix });
+            rest = &rest[end + 1..];
         }
-    }
-    // NOTE: It used to be possible for this function to produce an error.
-    //       Whilst it is no longer possible for it to do so, the signature remains Error<()> so
-    //       that in the future an error could be generated if needed.
-    fn get_out_name<'a>(
-        line: &'a str,
-        subbed: &'a str,
-        start_idx: usize,
-        template_len: usize,
-        sub_len: usize,
-        out_name: &mut String,
-    ) -> Error<()> {
-        let out = if let Some(end_idx) = line[(start_idx + template_len)..].find(' ') {
-            &subbed[start_idx..end_idx + start_idx + sub_len]
-        } else {
-            &subbed[start_idx..]
-        };
-        *out_name = out.to_string();
-        Ok(())
+        if !rest.is_empty() {
+            tokens.push(Token::Text(rest));
+        }
+        Ok(tokens)
     }
 
-    pub(crate) type Conf<'a> = HashMap<&'a str, Rep<'a>>;
-    // TODO(dk949): change String to String|&str
-    pub(crate) fn sub<'a>(inp: &'a str, conf: &Conf<'a>, out_name: &mut String) -> Error<String> {
-        let mut modified: Option<String> = None;
-        for (find, replace) in conf {
-            if let Some(idx) = modified
-                .as_ref()
-                .map(|s| s.as_str())
-                .unwrap_or(inp)
-                .find(find)
-            {
-                modified = match modified {
-                    Some(modified) => {
-                        let new_modified = modified.replace(find, replace.string);
-                        if replace.is_out {
-                            get_out_name(
-                                &modified,
-                                &new_modified,
-                                idx,
-                                find.len(),
-                                replace.string.len(),
-                                out_name,
-                            )?;
-                        }
-                        Some(new_modified)
-                    }
-                    None => {
-                        let new_modified = inp.replace(find, replace.string);
-                        if replace.is_out {
-                            get_out_name(
-                                inp,
-                                &new_modified,
-                                idx,
-                                find.len(),
-                                replace.string.len(),
-                                out_name,
-                            )?;
+    // NOTE: A lone list template string expands to one argument per element
+    pub(crate) fn expand(args: &[&str], conf: &Conf) -> Result<Vec<String>, String> {
+        let mut out = Vec::new();
+        for arg in args {
+            let tokens = tokenize(arg)?;
+            if let [Token::Var { name, suffix: "" }] = tokens[..] {
+                if let Some(Value::List(list)) = conf.get(name) {
+                    out.extend(list.iter().cloned());
+                    continue;
+                }
+            }
+            let mut arg = String::new();
+            for token in tokens {
+                match token {
+                    Token::Text(text) => arg += text,
+                    Token::Var { name, suffix } => {
+                        match conf
+                            .get(name)
+                            .ok_or_else(|| format!("unknown template string '%{name}%'"))?
+                        {
+                            Value::Str(s) => arg += s,
+                            Value::List(list) => arg += &shell::join(list),
                         }
-                        Some(new_modified)
+                        arg += suffix;
                     }
-                };
+                }
             }
+            out.push(arg);
         }
-        if let Some(modified) = modified {
-            Ok(modified)
-        } else {
-            Ok(inp.to_owned())
+        Ok(out)
+    }
+
+    pub(crate) fn suffixes<'a>(args: &[&'a str], name: &str) -> Vec<&'a str> {
+        args.iter()
+            .copied()
+            .filter_map(|arg| tokenize(arg).ok())
+            .flatten()
+            .filter_map(|token| match token {
+                Token::Var { name: n, suffix } if n == name => Some(suffix),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn conf() -> Conf<'static> {
+            Conf::from([
+                (INPUT_FILE, Value::Str("/tmp/main.c")),
+                (OUTPUT_FILE, Value::Str("/tmp/out")),
+            ])
+        }
+
+        #[test]
+        fn literal_percent() {
+            assert_eq!(expand(&["100%%"], &conf()).unwrap(), ["100%"]);
+            assert_eq!(
+                expand(&["%%INPUT_FILE%%"], &conf()).unwrap(),
+                ["%INPUT_FILE%"]
+            );
+        }
+
+        #[test]
+        fn suffix() {
+            assert_eq!(
+                expand(&["%OUTPUT_FILE:.o%"], &conf()).unwrap(),
+                ["/tmp/out.o"]
+            );
+            assert_eq!(
+                expand(&["-o", "%OUTPUT_FILE%.o"], &conf()).unwrap(),
+                ["-o", "/tmp/out.o"]
+            );
+            assert_eq!(
+                suffixes(&["%OUTPUT_FILE:.jar%", "%OUTPUT_FILE%"], OUTPUT_FILE),
+                [".jar", ""]
+            );
+        }
+
+        #[test]
+        fn list() {
+            let args = ["-O2".to_string(), "-D X=1".to_string()];
+            let conf = Conf::from([(COMPILER_ARGS, Value::List(&args))]);
+            assert_eq!(expand(&["%COMPILER_ARGS%"], &conf).unwrap(), args);
+            assert_eq!(
+                expand(&["cc %COMPILER_ARGS%"], &conf).unwrap(),
+                ["cc -O2 '-D X=1'"]
+            );
+        }
+
+        #[test]
+        fn errors() {
+            assert_eq!(
+                expand(&["%NOPE%"], &conf()).unwrap_err(),
+                "unknown template string '%NOPE%'"
+            );
+            assert!(expand(&["%INPUT_FILE"], &conf())
+                .unwrap_err()
+                .contains("unterminated"));
+            assert!(expand(&["%lower%"], &conf())
+                .unwrap_err()
+                .contains("invalid"));
         }
     }
 }
@@ -532,184 +849,2341 @@ impl Runner {
         });
         dep.map(|_| idx - 1)
     }
+    fn resolve_deps(self: &Self) -> Result<Vec<&'static str>, &'static [&'static str]> {
+        self.other_deps
+            .iter()
+            .map(|deps| Ok(deps[Self::check_dep_list(deps).ok_or(*deps)?]))
+            .collect()
+    }
     fn check_deps(self: &mut Self) -> Result<(), &'static [&'static str]> {
         self.exe_idx = if self.exe_deps.is_empty() {
             Self::NATIVE
         } else {
             Self::check_dep_list(self.exe_deps).ok_or(self.exe_deps)?
         };
-        for deps in self.other_deps {
-            Self::check_dep_list(*deps).ok_or(*deps)?;
-        }
+        self.resolve_deps()?;
         Ok(())
     }
 
-    fn run_aux(
-        cmds: &[&[&str]],
-        copmiler_args: &[String],
-        conf: &template::Conf,
-        out_name: &mut String,
-    ) -> Error<()> {
-        if cmds.len() == 0 {
-            return Ok(());
-        }
+    fn expand(self: &Self, args: &[&str], conf: &template::Conf) -> Error<Vec<String>> {
+        template::expand(args, conf)
+            .or_else(|e| dier!(Codes::RunnerError, "Runner '{}': {e}", self.name))
+    }
 
-        fn exec(cmd: &[&str], conf: &template::Conf, out_name: &mut String) -> Error<()> {
-            let mut it = cmd.iter();
-            let exe = it
-                .next()
-                .ok_or_else(|| dieo!(Codes::InternalError, "Unexpected empty command"))?;
-            let res = Command::new(template::sub(exe, conf, out_name)?)
-                .args(
-                    it.map(|s| template::sub(s, conf, out_name))
-                        .collect::<Error<Vec<_>>>()?,
+    // NOTE: Every reference to %OUTPUT_FILE% has to use the same suffix
+    fn out_suffix(self: &Self) -> Error<Option<&'static str>> {
+        use template::{suffixes, OUTPUT_FILE};
+        let setup = self
+            .setup
+            .iter()
+            .flat_map(|cmd| suffixes(cmd, OUTPUT_FILE))
+            .collect::<Vec<_>>();
+        let teardown = self
+            .teardown
+            .iter()
+            .flat_map(|cmd| suffixes(cmd, OUTPUT_FILE));
+        let mut all = setup.iter().copied().chain(teardown);
+        if let Some(first) = all.next() {
+            if let Some(other) = all.find(|s| *s != first) {
+                dier!(
+                    Codes::RunnerError,
+                    "Runner '{}': %{OUTPUT_FILE}% is used with different suffixes ('{first}' and '{other}')",
+                    self.name
                 )
-                .output()
-                .to_code(exe)?;
-            check_status_template(cmd, &res, Codes::CodeError, conf)?;
-            Ok(())
+            }
         }
+        Ok(setup.first().copied())
+    }
 
-        let mut cmds_it = cmds.iter().take(cmds.len() - 1);
-        while let Some(cmd) = cmds_it.next() {
-            exec(cmd, conf, out_name)?;
-        }
-        let cmd = cmds.last().ok_or_else(|| {
-            dieo!(
-                Codes::InternalError,
-                "Expected to have exactly one element in cmds_it"
+    // NOTE: Files included by the source are not covered, see `--rebuild`
+    fn build_key(self: &Self, file: &Path, deps: &[&str], compiler_args: &[String]) -> Error<u64> {
+        let mut hasher = StableHasher::new();
+        hasher.add(&fs::read(file).or_else(|e| {
+            dier!(
+                Codes::FileError,
+                "Could not read '{}': {e}",
+                file.to_string_lossy()
             )
-        })?;
+        })?);
+        hasher.add(self.name.as_bytes());
+        for arg in self.setup.iter().flat_map(|cmd| cmd.iter()) {
+            hasher.add(arg.as_bytes());
+        }
+        let exe = self.get_exe()?;
+        for dep in [exe.as_str()]
+            .into_iter()
+            .filter(|exe| !exe.is_empty())
+            .chain(deps.iter().copied())
+        {
+            let path = find_exe_path(dep).and_then(|path| fs::canonicalize(path).ok());
+            let modified = path
+                .as_ref()
+                .and_then(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+                .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok());
+            hasher.add(
+                path.as_ref()
+                    .map_or(&[][..], |path| path.as_os_str().as_bytes()),
+            );
+            hasher.add(&modified.map_or(0, |time| time.as_nanos()).to_le_bytes());
+        }
+        for arg in compiler_args {
+            hasher.add(arg.as_bytes());
+        }
+        Ok(hasher.0)
+    }
+
+    fn store_build(built: &Path, cached: &Path, prefix: &str) {
+        let Some(dir) = cached.parent() else { return };
+        let tmp = cached.with_extension(format!("{}.tmp", process::id()));
+        if let Err(e) = fs::copy(built, &tmp).and_then(|_| fs::rename(&tmp, cached)) {
+            let _ = fs::remove_file(&tmp);
+            println!("Could not cache the build: {e}");
+            return;
+        }
+        for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            if path != cached && entry.file_name().to_string_lossy().starts_with(prefix) {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
 
-        exec(
-            &[
-                *cmd,
-                &copmiler_args.iter().map(String::as_str).collect::<Vec<_>>(),
-            ]
-            .concat(),
-            conf,
-            out_name,
-        )?;
+    fn check_limits(limits: &limits::Limits, exited: &Exited) -> Error<()> {
+        if let Some(msg) = limits.exceeded(exited) {
+            dier!(Codes::LimitError, "{msg}")
+        }
+        if !exited.output.status.success() {
+            if let Some(hint) = limits.hint() {
+                println!("{hint}");
+            }
+        }
+        Ok(())
+    }
 
+    fn run_aux(
+        ctx: &Context,
+        cmds: &[Vec<String>],
+        phase: report::Phase,
+        input: &Path,
+        location: &mut Option<diagnostics::Location>,
+    ) -> Error<()> {
+        // NOTE: teardown is never limited, so that it can always clean up after setup
+        let limits = if phase == report::Phase::Setup && ctx.args.limit_setup {
+            ctx.args.limits
+        } else {
+            limits::Limits::default()
+        };
+        let work_dir = ctx.scratch.work_dir();
+        let since = signals::received();
+        for cmd in cmds {
+            let (exe, args) = cmd
+                .split_first()
+                .ok_or_else(|| dieo!(Codes::InternalError, "Unexpected empty command"))?;
+            let mut command = Command::new(exe);
+            limits.apply(&mut command);
+            if ctx.args.sandbox_setup {
+                sandbox::apply(&mut command, &work_dir)?;
+            }
+            let exited = command
+                .args(args)
+                .current_dir(&work_dir)
+                .stdin(Stdio::null())
+                .output_tee(
+                    Stream::Tee,
+                    if ctx.args.warnings {
+                        Stream::Tee
+                    } else {
+                        Stream::Capture
+                    },
+                    limits.timeout,
+                )
+                .to_code(exe)?;
+            signals::check(since)?;
+            ctx.report.add(phase, cmd, &exited);
+            let res = &exited.output;
+            if !res.status.success() {
+                *location = location.or(diagnostics::find(res, input));
+            }
+            let res = check_status(
+                &cmd.iter().map(String::as_str).collect::<Vec<_>>(),
+                res,
+                Codes::CodeError,
+            );
+            Self::check_limits(&limits, &exited)?;
+            res?;
+        }
         Ok(())
     }
 
-    fn run_exe(self: &Self, file: &Path, args: &[String]) -> Error<()> {
+    fn run_exe(
+        self: &Self,
+        ctx: &Context,
+        file: &Path,
+        exe_args_pre: &[String],
+        exe_args_post: &[String],
+        input: &Path,
+        location: &mut Option<diagnostics::Location>,
+    ) -> Error<process::ExitStatus> {
+        let args = ctx.args;
         let exe = self.get_exe()?;
+        let since = signals::received();
         let is_native = matches!(exe, Exe::Native);
         let mut cmd = Command::new(if is_native {
             file.to_str_or_die()?
         } else {
             exe.as_str()
         });
-        let res = if is_native {
-            cmd.args(self.exe_args_post)
+        args.limits.apply(&mut cmd);
+        if args.sandbox {
+            sandbox::apply(&mut cmd, &ctx.scratch.work_dir())?;
+        }
+        if is_native {
+            cmd.args(exe_args_post)
         } else {
-            cmd.args(self.exe_args_pre.iter())
-                .arg(file)
-                .args(self.exe_args_post.iter())
+            cmd.args(exe_args_pre).arg(file).args(exe_args_post)
         }
-        .args(args)
-        .stdin(Stdio::inherit())
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .output()
-        .to_code(if is_native {
-            file.to_str_or_die()?
+        .stdin(if let Some(stdin) = &args.prog_stdin {
+            Stdio::from(fs::File::open(stdin).or_else(|e| {
+                dier!(
+                    Codes::FileError,
+                    "Could not open '{}': {e}",
+                    stdin.to_string_lossy()
+                )
+            })?)
         } else {
-            exe.as_str()
-        })?;
-
-        check_status(
-            &[
-                &[exe.as_str()],
-                self.exe_args_pre,
-                &[&file.to_str_or_die()?],
-                self.exe_args_post,
-            ]
-            .concat(),
-            &res,
-            Codes::CodeError,
-        )?;
-        Ok(())
+            Stdio::inherit()
+        });
+        // NOTE: Streams which are not teed stay on the terminal
+        let json = args.format == Format::Json;
+        let stream = |tee| if tee { Stream::Tee } else { Stream::Inherit };
+        let mut exited = cmd
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .output_tee(
+                stream(json),
+                stream(json || args.interactive || args.watch),
+                args.limits.timeout,
+            )
+            .to_code(if is_native {
+                file.to_str_or_die()?
+            } else {
+                exe.as_str()
+            })?;
+        signals::check(since)?;
+        let command = [
+            &[exe.as_str()][..],
+            &exe_args_pre.iter().map(String::as_str).collect::<Vec<_>>(),
+            &[file.to_str_or_die()?],
+            &exe_args_post.iter().map(String::as_str).collect::<Vec<_>>(),
+        ]
+        .concat();
+        ctx.report.add(
+            report::Phase::Program,
+            &command[usize::from(is_native)..],
+            &exited,
+        );
+        let res = &mut exited.output;
+
+        if !res.status.success() {
+            *location = location.or(diagnostics::find(res, input));
+        }
+        // NOTE: The output has already been shown while the program was running
+        res.stdout.clear();
+        res.stderr.clear();
+        // NOTE: A failing program is not an error in itself, its status is returned either way
+        check_status(&command, res, Codes::CodeError).unwrap_or(());
+        Self::check_limits(&args.limits, &exited)?;
+        Ok(exited.output.status)
     }
 
-    // TODO(dk949): make it possible to refer to teh main executable and other dependencies through
-    //              template strings.
     fn run(
         self: &Self,
-        lang: &str,
+        ctx: &Context,
         file: &Path,
         compiler_args: &[String],
         prog_args: &[String],
-    ) -> Error<()> {
-        use template::*;
-        let out_file = cache_file_name(&env::temp_dir(), lang, "output_file", "");
-        let conf = Conf::from([
-            ("%INPUT_FILE%", Rep::new(file.to_str_or_die()?)),
-            ("%OUTPUT_FILE%", Rep::out(out_file.to_str_or_die()?)),
+        location: &mut Option<diagnostics::Location>,
+    ) -> Error<process::ExitStatus> {
+        use template::{suffixes, Conf, Value};
+        let Context {
+            args, lang, report, ..
+        } = *ctx;
+        *location = None;
+        // NOTE: setup runs inside of the work directory, so the input has to be an absolute path
+        let file = &path::absolute(file).or_else(|e| {
+            dier!(
+                Codes::FileError,
+                "Could not get the absolute path of '{}': {e}",
+                file.to_string_lossy()
+            )
+        })?;
+        let work_dir = ctx.scratch.work_dir();
+        let out_file = cache_file_name(&work_dir, lang, "output_file", "");
+        let cache_dir = args.cache_dir.join(CACHE_DIR);
+        for dir in [&work_dir, &cache_dir] {
+            fs::create_dir_all(dir).or_else(|e| {
+                dier!(
+                    Codes::FileError,
+                    "Could not create directory '{}': {e}",
+                    dir.to_string_lossy()
+                )
+            })?;
+        }
+        let input_dir = file
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let input_stem = file
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| {
+                dieo!(
+                    Codes::InternalError,
+                    "Could not get the name of the input file"
+                )
+            })?;
+        let exe = self.get_exe()?;
+        let deps = self
+            .resolve_deps()
+            .or_else(|deps| Runners::missing(deps).map(|_| Vec::new()))?;
+        let dep_names = (0..deps.len())
+            .map(|i| format!("DEP{i}"))
+            .collect::<Vec<_>>();
+        let mut conf = Conf::from([
+            (template::INPUT_FILE, Value::Str(file.to_str_or_die()?)),
+            (template::INPUT_DIR, Value::Str(input_dir.to_str_or_die()?)),
+            (template::INPUT_STEM, Value::Str(input_stem)),
+            (template::OUTPUT_FILE, Value::Str(out_file.to_str_or_die()?)),
+            (template::WORK_DIR, Value::Str(work_dir.to_str_or_die()?)),
+            (template::CACHE_DIR, Value::Str(cache_dir.to_str_or_die()?)),
+            (template::COMPILER_ARGS, Value::List(compiler_args)),
+            (template::PROG_ARGS, Value::List(prog_args)),
         ]);
-        let mut out_name = String::new();
-        Self::run_aux(self.setup, compiler_args, &conf, &mut out_name)?;
-        self.run_exe(
-            if !out_name.is_empty() {
-                Path::new(&out_name)
-            } else {
-                file
-            },
-            prog_args,
-        )
-        .unwrap_or(());
-        Self::run_aux(self.teardown, &[], &conf, &mut out_name)?;
-        Ok(())
-    }
-}
-
-struct Runners {
-    runners: &'static [Runner],
-}
-
-impl Runners {
-    fn new() -> Self {
-        Runners {
-            runners: include!(concat!(env!("OUT_DIR"), "/runners_list")),
+        if let Exe::Str(exe) = exe {
+            conf.insert(template::EXE, Value::Str(exe));
         }
-    }
-    fn get<'a>(self: &Self, name: &'a str, lang: &'a str) -> Option<Error<&'a Runner>> {
-        for r in self.runners {
-            if r.name == name {
-                if r.supported_langs.contains(&lang) {
-                    return Some(Ok(r));
-                }
+        conf.extend(
+            dep_names
+                .iter()
+                .zip(&deps)
+                .map(|(name, dep)| (name.as_str(), Value::Str(dep))),
+        );
+
+        let out_suffix = self.out_suffix()?;
+        let mut setup = self
+            .setup
+            .iter()
+            .map(|cmd| self.expand(cmd, &conf))
+            .collect::<Error<Vec<_>>>()?;
+        let exe_args_pre = self.expand(self.exe_args_pre, &conf)?;
+        let mut exe_args_post = self.expand(self.exe_args_post, &conf)?;
+        let teardown = self
+            .teardown
+            .iter()
+            .map(|cmd| self.expand(cmd, &conf))
+            .collect::<Error<Vec<_>>>()?;
+
+        let uses = |name| {
+            self.setup
+                .iter()
+                .copied()
+                .chain([self.exe_args_pre, self.exe_args_post])
+                .any(|args| !suffixes(args, name).is_empty())
+        };
+        if !uses(template::COMPILER_ARGS) {
+            if let Some(cmd) = setup.last_mut() {
+                cmd.extend(compiler_args.iter().cloned());
             }
         }
-        None
-    }
+        if !uses(template::PROG_ARGS) {
+            exe_args_post.extend(prog_args.iter().cloned());
+        }
 
-    fn runner_for_lang<'a>(self: &Self, lang: &'a str) -> Error<&Runner> {
-        for r in self.runners {
-            if r.default_for.contains(&lang) {
-                return Ok(r);
-            }
+        if args.sandbox || args.sandbox_setup {
+            sandbox::check(&work_dir)?;
         }
-        Err(Codes::InternalError)
-    }
 
-    fn determine<'a>(self: &Self, user_runner: Option<&'a String>, lang: &'a str) -> Error<Runner> {
-        let runner = if let Some(runner) = user_runner {
-            if let Some(r) = self.get(runner.as_str(), lang) {
-                r.or_else(|c| {
-                    dier!(
-                        c,
-                        "Specified runner '{runner}' cannot be used with '{lang}'"
-                    )
-                })?
-            } else {
-                dier!(Codes::RunnerError, "Unsupported runner '{runner}'")
-            }
+        let out_name = out_suffix.map(|suffix| format!("{}{suffix}", out_file.to_string_lossy()));
+        report.start(
+            lang,
+            self.name,
+            match exe {
+                Exe::Native => out_name
+                    .clone()
+                    .unwrap_or_else(|| file.to_string_lossy().into_owned()),
+                Exe::Str(exe) => find_exe_path(exe)
+                    .map_or_else(|| exe.to_string(), |exe| exe.to_string_lossy().into_owned()),
+            },
+        );
+        // NOTE: The cached copy is never run, so that it cannot be modified
+        let build_dir = cache_dir.join(BUILD_DIR);
+        let build_prefix = format!("{}.", add_prefix(lang, self.name));
+        let cached = match out_suffix {
+            Some(suffix) => Some(build_dir.join(format!(
+                "{build_prefix}{:016x}{suffix}",
+                self.build_key(file, &deps, compiler_args)?
+            ))),
+            None => None,
+        };
+        let hit = !args.rebuild
+            && cached
+                .as_ref()
+                .zip(out_name.as_ref())
+                .is_some_and(|(cached, out)| fs::copy(cached, out).is_ok());
+        let res = if hit {
+            report.cached_build();
+            Ok(())
+        } else {
+            Self::run_aux(ctx, &setup, report::Phase::Setup, file, location)
+        };
+        if let (Ok(()), false, Some(cached), Some(out)) = (&res, hit, &cached, &out_name) {
+            fs::create_dir_all(&build_dir)
+                .map(|_| Self::store_build(Path::new(out), cached, &build_prefix))
+                .unwrap_or_else(|e| println!("Could not cache the build: {e}"));
+        }
+        // NOTE: teardown runs whenever setup did, so that it can clean up after it
+        let res = if matches!(
+            res,
+            Ok(()) | Err(Codes::Interrupted) | Err(Codes::LimitError)
+        ) {
+            let status = res.and_then(|_| {
+                self.run_exe(
+                    ctx,
+                    out_name.as_ref().map(Path::new).unwrap_or(file),
+                    &exe_args_pre,
+                    &exe_args_post,
+                    file,
+                    location,
+                )
+            });
+            let teardown = Self::run_aux(ctx, &teardown, report::Phase::Teardown, file, location);
+            status.and_then(|status| teardown.map(|_| status))
+        } else {
+            res.map(|_| process::ExitStatus::default())
+        };
+        fs::remove_dir_all(&work_dir).or_else(|e| {
+            dier!(
+                Codes::FileError,
+                "Could not remove directory '{}': {e}",
+                work_dir.to_string_lossy()
+            )
+        })?;
+        res
+    }
+}
+
+mod report {
+    use super::*;
+    use std::cell::RefCell;
+    use std::os::fd::FromRawFd;
+
+    #[derive(PartialEq, Clone, Copy)]
+    pub(crate) enum Phase {
+        Setup,
+        Program,
+        Teardown,
+    }
+
+    struct Step {
+        phase: Phase,
+        command: Vec<String>,
+        status: process::ExitStatus,
+        stdout: Vec<u8>,
+        stderr: Vec<u8>,
+        wall: Duration,
+        usage: libc::rusage,
+    }
+
+    struct Run {
+        lang: String,
+        runner: String,
+        exe: String,
+        cached_build: bool,
+        steps: Vec<Step>,
+    }
+
+    #[derive(Default)]
+    pub(crate) struct Report {
+        run: RefCell<Option<Run>>,
+    }
+
+    impl Report {
+        pub(crate) fn start(self: &Self, lang: &str, runner: &str, exe: String) {
+            *self.run.borrow_mut() = Some(Run {
+                lang: lang.to_string(),
+                runner: runner.to_string(),
+                exe,
+                cached_build: false,
+                steps: Vec::new(),
+            });
+        }
+
+        pub(crate) fn cached_build(self: &Self) {
+            if let Some(run) = self.run.borrow_mut().as_mut() {
+                run.cached_build = true;
+            }
+        }
+
+        pub(crate) fn add<S: AsRef<str>>(
+            self: &Self,
+            phase: Phase,
+            command: &[S],
+            exited: &Exited,
+        ) {
+            if let Some(run) = self.run.borrow_mut().as_mut() {
+                run.steps.push(Step {
+                    phase,
+                    command: command.iter().map(|arg| arg.as_ref().to_string()).collect(),
+                    status: exited.output.status,
+                    stdout: exited.output.stdout.clone(),
+                    stderr: exited.output.stderr.clone(),
+                    wall: exited.wall,
+                    usage: exited.usage,
+                });
+            }
+        }
+
+        // NOTE: The rusage from wait4 includes the processes the command waited for
+        pub(crate) fn print_times(self: &Self) {
+            let run = self.run.borrow();
+            let Some(run) = run.as_ref() else { return };
+            let secs = |time: libc::timeval| {
+                format!(
+                    "{:.3}s",
+                    time.tv_sec as f64 + time.tv_usec as f64 / 1_000_000.0
+                )
+            };
+            let rows = run
+                .steps
+                .iter()
+                .map(|step| {
+                    let name = step.command.first().map_or("", |exe| {
+                        Path::new(exe)
+                            .file_name()
+                            .and_then(|name| name.to_str())
+                            .unwrap_or(exe)
+                    });
+                    [
+                        match step.phase {
+                            Phase::Setup => "setup",
+                            Phase::Program => "program",
+                            Phase::Teardown => "teardown",
+                        }
+                        .to_string(),
+                        name.to_string(),
+                        format!("{:.3}s", step.wall.as_secs_f64()),
+                        secs(step.usage.ru_utime),
+                        secs(step.usage.ru_stime),
+                        // NOTE: ru_maxrss is in kilobytes
+                        format!("{:.1}M", step.usage.ru_maxrss as f64 / 1024.0),
+                    ]
+                })
+                .collect::<Vec<_>>();
+            let header = ["Phase", "Command", "Wall", "User", "System", "Peak RSS"];
+            let widths = header.map(str::len).map(|len| len.max(8));
+            let widths = rows.iter().fold(widths, |mut widths, row| {
+                for (width, cell) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(cell.chars().count());
+                }
+                widths
+            });
+            eprintln!("\n{}", HLINE);
+            if run.cached_build {
+                eprintln!("Setup was skipped, the cached build was used");
+            }
+            for row in [header.map(String::from)].iter().chain(&rows) {
+                let line = row
+                    .iter()
+                    .zip(widths)
+                    .enumerate()
+                    .map(|(i, (cell, width))| {
+                        if i < 2 {
+                            format!("{cell:<width$}")
+                        } else {
+                            format!("{cell:>width$}")
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                eprintln!("{}", line.join("  ").trim_end());
+            }
+        }
+
+        pub(crate) fn write_json(
+            self: &Self,
+            mut out: impl Write,
+            res: &Error<process::ExitStatus>,
+        ) -> io::Result<()> {
+            let run = self.run.borrow();
+            let steps = |phase| {
+                run.iter()
+                    .flat_map(|run| &run.steps)
+                    .filter(move |step| step.phase == phase)
+                    .map(json::step)
+            };
+            let code = *res.as_ref().err().unwrap_or(&Codes::Ok);
+            let doc = json::object(&[
+                (
+                    "lang",
+                    json::optional(run.as_ref().map(|run| json::string(&run.lang))),
+                ),
+                (
+                    "runner",
+                    json::optional(run.as_ref().map(|run| json::string(&run.runner))),
+                ),
+                (
+                    "exe",
+                    json::optional(run.as_ref().map(|run| json::string(&run.exe))),
+                ),
+                (
+                    "cached_build",
+                    run.as_ref().is_some_and(|run| run.cached_build).to_string(),
+                ),
+                ("setup", json::list(steps(Phase::Setup))),
+                ("program", json::optional(steps(Phase::Program).next())),
+                ("teardown", json::list(steps(Phase::Teardown))),
+                ("code", (code as i32).to_string()),
+                ("code_name", json::string(&format!("{code:?}"))),
+            ]);
+            writeln!(out, "{doc}")
+        }
+    }
+
+    // NOTE: The JSON document has stdout to itself, everything else goes to stderr
+    pub(crate) fn redirect_stdout() -> io::Result<fs::File> {
+        io::stdout().flush()?;
+        unsafe {
+            let fd = libc::fcntl(libc::STDOUT_FILENO, libc::F_DUPFD_CLOEXEC, 3);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let out = fs::File::from_raw_fd(fd);
+            if libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(out)
+        }
+    }
+
+    mod json {
+        use super::*;
+
+        pub(super) fn string(s: &str) -> String {
+            let mut out = String::from("\"");
+            for c in s.chars() {
+                match c {
+                    '"' => out.push_str("\\\""),
+                    '\\' => out.push_str("\\\\"),
+                    '\n' => out.push_str("\\n"),
+                    '\r' => out.push_str("\\r"),
+                    '\t' => out.push_str("\\t"),
+                    c if u32::from(c) < 0x20 => out.push_str(&format!("\\u{:04x}", u32::from(c))),
+                    c => out.push(c),
+                }
+            }
+            out.push('"');
+            out
+        }
+
+        pub(super) fn optional(value: Option<impl ToString>) -> String {
+            value.map_or_else(|| "null".to_string(), |value| value.to_string())
+        }
+
+        pub(super) fn list(items: impl Iterator<Item = String>) -> String {
+            format!("[{}]", items.collect::<Vec<_>>().join(","))
+        }
+
+        pub(super) fn object(fields: &[(&str, String)]) -> String {
+            let fields = fields
+                .iter()
+                .map(|(name, value)| format!("{}:{value}", string(name)))
+                .collect::<Vec<_>>();
+            format!("{{{}}}", fields.join(","))
+        }
+
+        pub(super) fn step(step: &Step) -> String {
+            object(&[
+                ("command", list(step.command.iter().map(|arg| string(arg)))),
+                ("status", optional(step.status.code())),
+                ("signal", optional(step.status.signal())),
+                (
+                    "signal_name",
+                    optional(step.status.signal().map(|sig| string(&signals::name(sig)))),
+                ),
+                ("stdout", string(&String::from_utf8_lossy(&step.stdout))),
+                ("stderr", string(&String::from_utf8_lossy(&step.stderr))),
+                ("duration", step.wall.as_secs_f64().to_string()),
+            ])
+        }
+
+        #[cfg(test)]
+        mod tests {
+            use super::*;
+
+            #[test]
+            fn escapes() {
+                assert_eq!(string("plain"), r#""plain""#);
+                assert_eq!(string(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+                assert_eq!(string("a\nb\r\tc"), r#""a\nb\r\tc""#);
+            }
+
+            #[test]
+            fn control_characters() {
+                assert_eq!(
+                    string("\0\x07\x1b[0m\x1f"),
+                    r#""\u0000\u0007\u001b[0m\u001f""#
+                );
+                assert_eq!(string("\x7f é ✓"), "\"\x7f é ✓\"");
+            }
+        }
+    }
+}
+
+mod shell {
+    // NOTE: POSIX shell quoting rules, without any expansions
+    pub(crate) fn split(line: &str) -> Result<Vec<String>, String> {
+        let mut words = Vec::new();
+        let mut word: Option<String> = None;
+        let mut it = line.chars();
+        while let Some(c) = it.next() {
+            match c {
+                c if c.is_whitespace() => words.extend(word.take()),
+                '\'' => {
+                    let word = word.get_or_insert_with(String::new);
+                    loop {
+                        match it.next() {
+                            Some('\'') => break,
+                            Some(c) => word.push(c),
+                            None => return Err("unterminated single quote".to_string()),
+                        }
+                    }
+                }
+                '"' => {
+                    let word = word.get_or_insert_with(String::new);
+                    loop {
+                        match it.next() {
+                            Some('"') => break,
+                            Some('\\') => match it.next() {
+                                Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                                Some('\n') => {}
+                                Some(c) => {
+                                    word.push('\\');
+                                    word.push(c);
+                                }
+                                None => return Err("unterminated double quote".to_string()),
+                            },
+                            Some(c) => word.push(c),
+                            None => return Err("unterminated double quote".to_string()),
+                        }
+                    }
+                }
+                '\\' => match it.next() {
+                    Some('\n') => {}
+                    Some(c) => word.get_or_insert_with(String::new).push(c),
+                    None => return Err("trailing backslash".to_string()),
+                },
+                c => word.get_or_insert_with(String::new).push(c),
+            }
+        }
+        words.extend(word);
+        Ok(words)
+    }
+
+    fn quote(word: &str) -> String {
+        if !word.is_empty()
+            && word
+                .chars()
+                .all(|c| c.is_alphanumeric() || "-_=+.,:/@%".contains(c))
+        {
+            word.to_string()
+        } else {
+            format!("'{}'", word.replace('\'', "'\\''"))
+        }
+    }
+
+    pub(crate) fn join(words: &[String]) -> String {
+        words.iter().map(|w| quote(w)).collect::<Vec<_>>().join(" ")
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn words() {
+            assert_eq!(split("  a  b\tc\n").unwrap(), ["a", "b", "c"]);
+            assert!(split("   ").unwrap().is_empty());
+        }
+
+        #[test]
+        fn quotes() {
+            assert_eq!(split("'a b' \"c d\"").unwrap(), ["a b", "c d"]);
+            assert_eq!(split("a'b'\"c\"d").unwrap(), ["abcd"]);
+            assert_eq!(split("'' \"\"").unwrap(), ["", ""]);
+            assert_eq!(split("'\"' \"'\"").unwrap(), ["\"", "'"]);
+            assert_eq!(split(r"'a\b'").unwrap(), [r"a\b"]);
+        }
+
+        #[test]
+        fn escapes() {
+            assert_eq!(split(r"a\ b \'c").unwrap(), ["a b", "'c"]);
+            assert_eq!(split(r#""\"\\\$\`""#).unwrap(), [r#""\$`"#]);
+            assert_eq!(split(r#""\n""#).unwrap(), [r"\n"]);
+            assert_eq!(split("a\\\nb").unwrap(), ["ab"]);
+        }
+
+        #[test]
+        fn errors() {
+            assert_eq!(split("'a").unwrap_err(), "unterminated single quote");
+            assert_eq!(split("\"a").unwrap_err(), "unterminated double quote");
+            assert_eq!(split("\"a\\").unwrap_err(), "unterminated double quote");
+            assert_eq!(split("a\\").unwrap_err(), "trailing backslash");
+        }
+
+        #[test]
+        fn round_trip() {
+            let words = ["a", "b c", "it's", "", "$HOME"].map(String::from);
+            assert_eq!(split(&join(&words)).unwrap(), words);
+        }
+    }
+}
+
+mod signals {
+    use super::*;
+    use std::os::unix::process::CommandExt;
+    use std::sync::atomic::AtomicI32;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering::SeqCst;
+
+    static RECEIVED: AtomicUsize = AtomicUsize::new(0);
+    static SIGNAL: AtomicI32 = AtomicI32::new(0);
+    static CHILD: AtomicI32 = AtomicI32::new(0);
+    // NOTE: The child which the terminal was handed over to
+    static FOREGROUND: AtomicI32 = AtomicI32::new(0);
+
+    extern "C" fn handler(sig: libc::c_int) {
+        SIGNAL.store(sig, SeqCst);
+        RECEIVED.fetch_add(1, SeqCst);
+        let child = CHILD.load(SeqCst);
+        if child > 0 {
+            unsafe { libc::kill(-child, sig) };
+        }
+    }
+
+    // NOTE: SA_RESTART is not set, so that a blocking read is interrupted
+    pub(crate) fn install() {
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = handler as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            for sig in [libc::SIGINT, libc::SIGTERM] {
+                libc::sigaction(sig, &action, ptr::null_mut());
+            }
+        }
+    }
+
+    pub(crate) fn name(sig: i32) -> String {
+        match sig {
+            libc::SIGHUP => "SIGHUP",
+            libc::SIGINT => "SIGINT",
+            libc::SIGQUIT => "SIGQUIT",
+            libc::SIGILL => "SIGILL",
+            libc::SIGTRAP => "SIGTRAP",
+            libc::SIGABRT => "SIGABRT",
+            libc::SIGBUS => "SIGBUS",
+            libc::SIGFPE => "SIGFPE",
+            libc::SIGKILL => "SIGKILL",
+            libc::SIGUSR1 => "SIGUSR1",
+            libc::SIGSEGV => "SIGSEGV",
+            libc::SIGUSR2 => "SIGUSR2",
+            libc::SIGPIPE => "SIGPIPE",
+            libc::SIGALRM => "SIGALRM",
+            libc::SIGTERM => "SIGTERM",
+            libc::SIGXCPU => "SIGXCPU",
+            libc::SIGXFSZ => "SIGXFSZ",
+            libc::SIGSYS => "SIGSYS",
+            _ => return format!("signal {sig}"),
+        }
+        .to_string()
+    }
+
+    pub(crate) fn received() -> usize {
+        RECEIVED.load(SeqCst)
+    }
+
+    pub(crate) fn check(since: usize) -> Error<()> {
+        if received() > since {
+            dier!(
+                Codes::Interrupted,
+                "Interrupted by {}",
+                name(SIGNAL.load(SeqCst))
+            )
+        }
+        Ok(())
+    }
+
+    fn set_foreground(fd: libc::c_int, pgid: libc::pid_t) {
+        // NOTE: SIGTTOU is blocked, otherwise a background caller would be stopped
+        unsafe {
+            let mut set: libc::sigset_t = mem::zeroed();
+            let mut old: libc::sigset_t = mem::zeroed();
+            libc::sigemptyset(&mut set);
+            libc::sigaddset(&mut set, libc::SIGTTOU);
+            libc::pthread_sigmask(libc::SIG_BLOCK, &set, &mut old);
+            libc::tcsetpgrp(fd, pgid);
+            libc::pthread_sigmask(libc::SIG_SETMASK, &old, ptr::null_mut());
+        }
+    }
+
+    pub(crate) fn in_foreground() -> bool {
+        io::stdin().is_terminal()
+            && unsafe { libc::tcgetpgrp(libc::STDIN_FILENO) == libc::getpgrp() }
+    }
+
+    // NOTE: Has to happen before exec, a background child would be stopped by the terminal
+    pub(crate) fn take_terminal() {
+        unsafe {
+            let fd = libc::open(
+                c"/dev/tty".as_ptr(),
+                libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC,
+            );
+            if fd >= 0 {
+                set_foreground(fd, libc::getpgrp());
+                libc::close(fd);
+            }
+        }
+    }
+
+    // NOTE: Children get their own process group, so that signals reach everything they start
+    pub(crate) fn spawn(cmd: &mut Command) -> io::Result<process::Child> {
+        let since = received();
+        let foreground = in_foreground();
+        if foreground {
+            unsafe {
+                cmd.pre_exec(|| {
+                    take_terminal();
+                    Ok(())
+                });
+            }
+        }
+        let child = cmd.process_group(0).spawn()?;
+        let pid = child.id() as libc::pid_t;
+        CHILD.store(pid, SeqCst);
+        if received() > since {
+            unsafe { libc::kill(-pid, SIGNAL.load(SeqCst)) };
+        }
+        if foreground {
+            FOREGROUND.store(pid, SeqCst);
+        }
+        Ok(child)
+    }
+
+    pub(crate) fn reaped(pid: u32, status: &process::ExitStatus) {
+        let pid = pid as libc::pid_t;
+        let _ = CHILD.compare_exchange(pid, 0, SeqCst, SeqCst);
+        if FOREGROUND.compare_exchange(pid, 0, SeqCst, SeqCst).is_ok() {
+            set_foreground(libc::STDIN_FILENO, unsafe { libc::getpgrp() });
+            // NOTE: Ctrl-C was only delivered to the child, act as if ruc received it too.
+            if status.signal() == Some(libc::SIGINT) {
+                SIGNAL.store(libc::SIGINT, SeqCst);
+                RECEIVED.fetch_add(1, SeqCst);
+            }
+        }
+    }
+
+    // NOTE: The child was stopped (e.g. by Ctrl-Z), stop ruc too and continue the child with it
+    fn suspend(pid: libc::pid_t) {
+        if FOREGROUND.compare_exchange(pid, 0, SeqCst, SeqCst).is_ok() {
+            set_foreground(libc::STDIN_FILENO, unsafe { libc::getpgrp() });
+        }
+        unsafe { libc::raise(libc::SIGTSTP) };
+        if in_foreground() {
+            set_foreground(libc::STDIN_FILENO, pid);
+            FOREGROUND.store(pid, SeqCst);
+        }
+        unsafe { libc::kill(-pid, libc::SIGCONT) };
+    }
+
+    // NOTE: Not reaped, so that the pid is not reused while the watchdog may signal it
+    pub(crate) fn wait_exited(child: &process::Child) -> io::Result<()> {
+        loop {
+            let mut info: libc::siginfo_t = unsafe { mem::zeroed() };
+            let res = unsafe {
+                libc::waitid(
+                    libc::P_PID,
+                    child.id(),
+                    &mut info,
+                    libc::WEXITED | libc::WSTOPPED | libc::WNOWAIT,
+                )
+            };
+            if res == 0 && info.si_code == libc::CLD_STOPPED {
+                suspend(child.id() as libc::pid_t);
+                continue;
+            }
+            if res == 0 {
+                return Ok(());
+            }
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::Interrupted {
+                return Err(e);
+            }
+        }
+    }
+
+    pub(crate) fn has_exited(child: &process::Child) -> io::Result<bool> {
+        let mut info: libc::siginfo_t = unsafe { mem::zeroed() };
+        let res = unsafe {
+            libc::waitid(
+                libc::P_PID,
+                child.id(),
+                &mut info,
+                libc::WEXITED | libc::WNOWAIT | libc::WNOHANG,
+            )
+        };
+        if res != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { info.si_pid() } != 0)
+    }
+
+    pub(crate) fn reap(child: &process::Child) -> io::Result<(process::ExitStatus, libc::rusage)> {
+        loop {
+            let mut status = 0;
+            let mut usage: libc::rusage = unsafe { mem::zeroed() };
+            if unsafe { libc::wait4(child.id() as libc::pid_t, &mut status, 0, &mut usage) } >= 0 {
+                let status = process::ExitStatus::from_raw(status);
+                reaped(child.id(), &status);
+                return Ok((status, usage));
+            }
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::Interrupted {
+                return Err(e);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sandbox {
+    use super::*;
+    use std::os::unix::process::CommandExt;
+
+    const NAMESPACES: libc::c_int =
+        libc::CLONE_NEWUSER | libc::CLONE_NEWNS | libc::CLONE_NEWNET | libc::CLONE_NEWPID;
+
+    // NOTE: Not exported by libc
+    #[repr(C)]
+    struct MountAttr {
+        attr_set: u64,
+        attr_clr: u64,
+        propagation: u64,
+        userns_fd: u64,
+    }
+    const MOUNT_ATTR_RDONLY: u64 = 0x1;
+    const AT_RECURSIVE: libc::c_uint = 0x8000;
+
+    fn last_error<T>() -> io::Result<T> {
+        Err(io::Error::last_os_error())
+    }
+
+    unsafe fn write_file(path: &CStr, content: &[u8]) -> io::Result<()> {
+        let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+        if fd < 0 {
+            return last_error();
+        }
+        let res = libc::write(fd, content.as_ptr().cast(), content.len());
+        libc::close(fd);
+        if res != content.len() as isize {
+            return last_error();
+        }
+        Ok(())
+    }
+
+    unsafe fn mount(
+        src: Option<&CStr>,
+        target: &CStr,
+        fstype: Option<&CStr>,
+        flags: libc::c_ulong,
+    ) -> io::Result<()> {
+        let ptr = |s: Option<&CStr>| s.map_or(ptr::null(), CStr::as_ptr);
+        if libc::mount(ptr(src), target.as_ptr(), ptr(fstype), flags, ptr::null()) != 0 {
+            return last_error();
+        }
+        Ok(())
+    }
+
+    unsafe fn set_read_only(path: &CStr, read_only: bool) -> io::Result<()> {
+        let attr = MountAttr {
+            attr_set: if read_only { MOUNT_ATTR_RDONLY } else { 0 },
+            attr_clr: if read_only { 0 } else { MOUNT_ATTR_RDONLY },
+            propagation: 0,
+            userns_fd: 0,
+        };
+        if libc::syscall(
+            libc::SYS_mount_setattr,
+            libc::AT_FDCWD,
+            path.as_ptr(),
+            AT_RECURSIVE,
+            &attr as *const MountAttr,
+            mem::size_of::<MountAttr>(),
+        ) != 0
+        {
+            return last_error();
+        }
+        Ok(())
+    }
+
+    unsafe fn close_fds_except(keep: libc::c_int) {
+        for (first, last) in [(3, keep - 1), (keep + 1, libc::c_int::MAX)] {
+            if first <= last
+                && libc::syscall(
+                    libc::SYS_close_range,
+                    first as libc::c_uint,
+                    last as libc::c_uint,
+                    0,
+                ) != 0
+            {
+                for fd in first..last.min(1024) {
+                    libc::close(fd);
+                }
+            }
+        }
+    }
+
+    unsafe fn wait_for(pid: libc::pid_t) -> libc::c_int {
+        let mut status = 0;
+        while libc::waitpid(pid, &mut status, 0) < 0 {
+            if *libc::__errno_location() != libc::EINTR {
+                return 0;
+            }
+        }
+        status
+    }
+
+    const STEPS: [&str; 7] = [
+        "create the namespaces",
+        "map the user and group ids",
+        "make the file system read-only",
+        "mount the scratch directory",
+        "make the scratch directory writable",
+        "enter the working directory",
+        "start the init process",
+    ];
+
+    // NOTE: Only the error code gets back from the child, so it encodes the failed step
+    fn failed(step: usize) -> impl Fn(io::Error) -> io::Error {
+        move |e| {
+            io::Error::from_raw_os_error(((step as i32 + 1) << 16) | e.raw_os_error().unwrap_or(0))
+        }
+    }
+
+    pub(crate) fn failed_step(e: &io::Error) -> Option<String> {
+        let code = e.raw_os_error()?;
+        let step = STEPS.get(((code >> 16) as usize).checked_sub(1)?)?;
+        let e = io::Error::from_raw_os_error(code & 0xffff);
+        Some(match e.raw_os_error() {
+            Some(libc::ENOSYS) => format!("could not {step}: {e} (Linux 5.12 or newer is needed)"),
+            Some(libc::EPERM | libc::EACCES | libc::EINVAL) if step == &STEPS[0] => format!(
+                "could not {step}: {e}. Unprivileged user namespaces may be disabled \
+                 (see /proc/sys/kernel/unprivileged_userns_clone and \
+                 /proc/sys/user/max_user_namespaces)"
+            ),
+            _ => format!("could not {step}: {e}"),
+        })
+    }
+
+    struct Setup {
+        uid_map: String,
+        gid_map: String,
+        writable: CString,
+        cwd: Vec<libc::c_char>,
+    }
+
+    impl Setup {
+        fn new(writable: &Path) -> Error<Self> {
+            let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+            Ok(Setup {
+                uid_map: format!("{uid} {uid} 1"),
+                gid_map: format!("{gid} {gid} 1"),
+                writable: CString::new(writable.as_os_str().as_bytes())
+                    .or_else(|e| dier!(Codes::SandboxError, "Invalid scratch directory: {e}"))?,
+                cwd: vec![0; libc::PATH_MAX as usize],
+            })
+        }
+
+        unsafe fn enter(self: &mut Self) -> io::Result<()> {
+            if libc::unshare(NAMESPACES) != 0 {
+                return last_error().map_err(failed(0));
+            }
+            write_file(c"/proc/self/setgroups", b"deny")
+                .and_then(|_| write_file(c"/proc/self/uid_map", self.uid_map.as_bytes()))
+                .and_then(|_| write_file(c"/proc/self/gid_map", self.gid_map.as_bytes()))
+                .map_err(failed(1))?;
+            mount(None, c"/", None, libc::MS_REC | libc::MS_PRIVATE)
+                .and_then(|_| set_read_only(c"/", true))
+                .map_err(failed(2))?;
+            let writable = &self.writable;
+            mount(Some(writable), writable, None, libc::MS_BIND | libc::MS_REC)
+                .map_err(failed(3))?;
+            // NOTE: Remounting would have to repeat the locked flags (e.g. nosuid on /tmp)
+            set_read_only(writable, false).map_err(failed(4))?;
+            // NOTE: The working directory still refers to the read-only mount
+            if libc::getcwd(self.cwd.as_mut_ptr(), self.cwd.len()).is_null()
+                || libc::chdir(self.cwd.as_ptr()) != 0
+            {
+                return last_error().map_err(failed(5));
+            }
+            Ok(())
+        }
+    }
+
+    // NOTE: Sets the sandbox up in a throwaway child, to fail before anything is run
+    pub(crate) fn check(writable: &Path) -> Error<()> {
+        let mut setup = Setup::new(writable)?;
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+            dier!(
+                Codes::SandboxError,
+                "Could not create the sandbox: {}",
+                io::Error::last_os_error()
+            )
+        }
+        let [read_fd, write_fd] = fds;
+        let pid = unsafe { libc::fork() };
+        if pid == 0 {
+            unsafe {
+                libc::close(read_fd);
+                let res = if libc::chdir(setup.writable.as_ptr()) != 0 {
+                    last_error().map_err(failed(5))
+                } else {
+                    setup.enter()
+                };
+                let code = res.err().and_then(|e| e.raw_os_error()).unwrap_or(0);
+                libc::write(
+                    write_fd,
+                    (&code as *const libc::c_int).cast(),
+                    mem::size_of::<libc::c_int>(),
+                );
+                libc::_exit(0);
+            }
+        }
+        let fork_error = io::Error::last_os_error();
+        let mut code: libc::c_int = 0;
+        unsafe {
+            libc::close(write_fd);
+            if pid > 0 {
+                while libc::read(
+                    read_fd,
+                    (&mut code as *mut libc::c_int).cast(),
+                    mem::size_of::<libc::c_int>(),
+                ) < 0
+                    && *libc::__errno_location() == libc::EINTR
+                {}
+                wait_for(pid);
+            }
+            libc::close(read_fd);
+        }
+        if pid < 0 {
+            dier!(
+                Codes::SandboxError,
+                "Could not create the sandbox: {fork_error}"
+            )
+        }
+        if let Some(msg) = failed_step(&io::Error::from_raw_os_error(code)) {
+            dier!(Codes::SandboxError, "Could not create the sandbox: {msg}")
+        }
+        Ok(())
+    }
+
+    // NOTE: PID 1 of a PID namespace ignores signals it has no handler for, so it forks the command
+    pub(crate) fn apply(cmd: &mut Command, writable: &Path) -> Error<()> {
+        let mut setup = Setup::new(writable)?;
+        cmd.env("TMPDIR", setup.writable.to_string_lossy().as_ref());
+        let foreground = signals::in_foreground();
+        unsafe {
+            cmd.pre_exec(move || {
+                // NOTE: The process group cannot be referred to from inside of the namespace
+                if foreground {
+                    signals::take_terminal();
+                }
+                for sig in [libc::SIGINT, libc::SIGTERM] {
+                    libc::signal(sig, libc::SIG_DFL);
+                }
+                setup.enter()?;
+
+                let mut fds = [0; 2];
+                if libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) != 0 {
+                    return last_error().map_err(failed(6));
+                }
+                let [read_fd, write_fd] = fds;
+                let init = libc::fork();
+                if init < 0 {
+                    return last_error().map_err(failed(6));
+                }
+                if init == 0 {
+                    libc::close(read_fd);
+                    libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL);
+                    // NOTE: Not being able to hide the other processes is not fatal
+                    let _ = mount(
+                        Some(c"proc"),
+                        c"/proc",
+                        Some(c"proc"),
+                        libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC,
+                    );
+                    let child = libc::fork();
+                    if child < 0 {
+                        return last_error();
+                    }
+                    if child == 0 {
+                        libc::close(write_fd);
+                        return Ok(());
+                    }
+                    close_fds_except(write_fd);
+                    let status = wait_for(child);
+                    libc::write(
+                        write_fd,
+                        (&status as *const libc::c_int).cast(),
+                        mem::size_of::<libc::c_int>(),
+                    );
+                    libc::_exit(0);
+                }
+                libc::close(write_fd);
+                close_fds_except(read_fd);
+                // NOTE: Signals sent to the process group reach the command directly
+                for sig in [libc::SIGINT, libc::SIGTERM] {
+                    libc::signal(sig, libc::SIG_IGN);
+                }
+                let mut status: libc::c_int = 0;
+                let len = libc::read(
+                    read_fd,
+                    (&mut status as *mut libc::c_int).cast(),
+                    mem::size_of::<libc::c_int>(),
+                );
+                let init_status = wait_for(init);
+                if len != mem::size_of::<libc::c_int>() as isize {
+                    status = init_status;
+                }
+                if libc::WIFSIGNALED(status) {
+                    let sig = libc::WTERMSIG(status);
+                    let no_core = libc::rlimit {
+                        rlim_cur: 0,
+                        rlim_max: 0,
+                    };
+                    libc::setrlimit(libc::RLIMIT_CORE, &no_core);
+                    libc::signal(sig, libc::SIG_DFL);
+                    let mut set = mem::zeroed();
+                    libc::sigemptyset(&mut set);
+                    libc::sigaddset(&mut set, sig);
+                    libc::sigprocmask(libc::SIG_UNBLOCK, &set, ptr::null_mut());
+                    libc::kill(libc::getpid(), sig);
+                    libc::_exit(128 + sig);
+                }
+                libc::_exit(if libc::WIFEXITED(status) {
+                    libc::WEXITSTATUS(status)
+                } else {
+                    1
+                })
+            });
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn steps() {
+            let e = failed(3)(io::Error::from_raw_os_error(libc::EPERM));
+            assert_eq!(
+                failed_step(&e).unwrap(),
+                format!(
+                    "could not mount the scratch directory: {}",
+                    io::Error::from_raw_os_error(libc::EPERM)
+                )
+            );
+            let e = failed(2)(io::Error::from_raw_os_error(libc::ENOSYS));
+            assert!(failed_step(&e).unwrap().contains("Linux 5.12"));
+            assert!(failed_step(&io::Error::from_raw_os_error(libc::ENOENT)).is_none());
+            assert!(failed_step(&io::Error::other("not an os error")).is_none());
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sandbox {
+    use super::*;
+
+    pub(crate) fn check(_: &Path) -> Error<()> {
+        dier!(Codes::SandboxError, "--sandbox is only supported on Linux")
+    }
+
+    pub(crate) fn failed_step(_: &io::Error) -> Option<String> {
+        None
+    }
+
+    pub(crate) fn apply(_: &mut Command, writable: &Path) -> Error<()> {
+        check(writable)
+    }
+}
+
+mod limits {
+    use super::*;
+    use std::os::unix::process::CommandExt;
+    use std::sync::Arc;
+    use std::sync::Condvar;
+    use std::sync::Mutex;
+
+    #[derive(Clone, Copy, Default)]
+    pub(crate) struct Limits {
+        pub timeout: Option<Duration>,
+        pub memory: Option<u64>,
+        pub cpu: Option<u64>,
+        pub procs: Option<u64>,
+    }
+
+    // NOTE: Sizes are in bytes, optionally with a K, M or G suffix (powers of 1024)
+    pub(crate) fn parse_size(s: &str) -> Option<u64> {
+        let (num, mul) = match s.chars().last()?.to_ascii_uppercase() {
+            'K' => (&s[..s.len() - 1], 1 << 10),
+            'M' => (&s[..s.len() - 1], 1 << 20),
+            'G' => (&s[..s.len() - 1], 1 << 30),
+            _ => (s, 1),
+        };
+        num.parse::<u64>().ok()?.checked_mul(mul)
+    }
+
+    fn format_size(size: u64) -> String {
+        match size {
+            s if s % (1 << 30) == 0 => format!("{}G", s >> 30),
+            s if s % (1 << 20) == 0 => format!("{}M", s >> 20),
+            s if s % (1 << 10) == 0 => format!("{}K", s >> 10),
+            s => format!("{s} bytes"),
+        }
+    }
+
+    impl Limits {
+        // NOTE: Hard limits are only lowered, raising them is not allowed
+        pub(crate) fn apply(self: &Self, cmd: &mut Command) {
+            let limits = [
+                (libc::RLIMIT_AS, self.memory, 0),
+                // NOTE: SIGXCPU is sent at the soft limit and SIGKILL at the hard one
+                (libc::RLIMIT_CPU, self.cpu, 1),
+                (libc::RLIMIT_NPROC, self.procs, 0),
+            ];
+            if limits.iter().all(|(_, limit, _)| limit.is_none()) {
+                return;
+            }
+            unsafe {
+                cmd.pre_exec(move || {
+                    for (resource, limit, extra) in limits {
+                        let limit = if let Some(limit) = limit {
+                            limit
+                        } else {
+                            continue;
+                        };
+                        let mut current = mem::zeroed();
+                        if libc::getrlimit(resource, &mut current) != 0 {
+                            return Err(io::Error::last_os_error());
+                        }
+                        let hard = current.rlim_max.min(limit.saturating_add(extra));
+                        let new = libc::rlimit {
+                            rlim_cur: hard.min(limit),
+                            rlim_max: hard,
+                        };
+                        if libc::setrlimit(resource, &new) != 0 {
+                            return Err(io::Error::last_os_error());
+                        }
+                    }
+                    Ok(())
+                });
+            }
+        }
+
+        // NOTE: Hitting the memory or process limit cannot be detected reliably
+        pub(crate) fn exceeded(self: &Self, exited: &Exited) -> Option<String> {
+            if exited.timed_out {
+                return self
+                    .timeout
+                    .map(|t| format!("Timeout of {}s was hit", t.as_secs_f64()));
+            }
+            let cpu = self.cpu?;
+            let used = exited.usage.ru_utime.tv_sec + exited.usage.ru_stime.tv_sec;
+            match exited.output.status.signal() {
+                Some(libc::SIGXCPU) => {}
+                Some(libc::SIGKILL) if used as u64 >= cpu => {}
+                _ => return None,
+            }
+            Some(format!("CPU time limit of {cpu}s was hit"))
+        }
+
+        pub(crate) fn hint(self: &Self) -> Option<String> {
+            let mut hints = Vec::new();
+            if let Some(memory) = self.memory {
+                hints.push(format!("the memory limit ({})", format_size(memory)));
+            }
+            if let Some(procs) = self.procs {
+                hints.push(format!("the process limit ({procs})"));
+            }
+            if hints.is_empty() {
+                None
+            } else {
+                Some(format!("The program may have hit {}", hints.join(" or ")))
+            }
+        }
+    }
+
+    // NOTE: Kills the process group of `pid` if it is still running after `timeout`
+    pub(crate) struct Watchdog {
+        done: Arc<(Mutex<bool>, Condvar)>,
+        thread: thread::JoinHandle<bool>,
+    }
+
+    impl Watchdog {
+        pub(crate) fn start(pid: u32, timeout: Duration) -> Self {
+            let done = Arc::new((Mutex::new(false), Condvar::new()));
+            let thread = thread::spawn({
+                let done = done.clone();
+                move || {
+                    let (lock, cvar) = &*done;
+                    let guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+                    let (guard, res) = cvar
+                        .wait_timeout_while(guard, timeout, |done| !*done)
+                        .unwrap_or_else(|e| e.into_inner());
+                    if res.timed_out() && !*guard {
+                        unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) };
+                        return true;
+                    }
+                    false
+                }
+            });
+            Watchdog { done, thread }
+        }
+
+        pub(crate) fn finish(self: Self) -> bool {
+            let (lock, cvar) = &*self.done;
+            *lock.lock().unwrap_or_else(|e| e.into_inner()) = true;
+            cvar.notify_one();
+            self.thread.join().unwrap_or(false)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn sizes() {
+            assert_eq!(parse_size("123"), Some(123));
+            assert_eq!(parse_size("4k"), Some(4 << 10));
+            assert_eq!(parse_size("4K"), Some(4 << 10));
+            assert_eq!(parse_size("256M"), Some(256 << 20));
+            assert_eq!(parse_size("2g"), Some(2 << 30));
+        }
+
+        #[test]
+        fn invalid_sizes() {
+            assert_eq!(parse_size(""), None);
+            assert_eq!(parse_size("K"), None);
+            assert_eq!(parse_size("-1"), None);
+            assert_eq!(parse_size("1.5G"), None);
+            assert_eq!(parse_size("1T"), None);
+            assert_eq!(parse_size("99999999999999999G"), None);
+        }
+    }
+}
+
+mod diagnostics {
+    use super::*;
+
+    #[derive(Clone, Copy)]
+    pub(crate) struct Location {
+        pub line: usize,
+        pub column: Option<usize>,
+    }
+
+    fn number(s: &str) -> Option<(usize, &str)> {
+        let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        Some((s[..end].parse().ok()?, &s[end..]))
+    }
+
+    // NOTE: `File "path", line N` from python tracebacks, the last frame is the closest
+    fn traceback(output: &str, name: &str) -> Option<Location> {
+        output.lines().rev().find_map(|line| {
+            let (path, rest) = line.trim_start().strip_prefix("File \"")?.split_once('"')?;
+            if Path::new(path).file_name()? != name {
+                return None;
+            }
+            let (line, _) = number(rest.strip_prefix(", line ")?)?;
+            Some(Location { line, column: None })
+        })
+    }
+
+    // NOTE: `path:line:col:` from gcc, clang, rustc, ghc, go and most other compilers
+    fn file_line_col(output: &str, name: &str) -> Option<Location> {
+        output.match_indices(name).find_map(|(idx, _)| {
+            if output[..idx]
+                .chars()
+                .next_back()
+                .is_some_and(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+            {
+                return None;
+            }
+            let (line, rest) = number(output[idx + name.len()..].strip_prefix(':')?)?;
+            let column = rest.strip_prefix(':').and_then(number).map(|(col, _)| col);
+            Some(Location { line, column })
+        })
+    }
+
+    pub(crate) fn find(res: &process::Output, file: &Path) -> Option<Location> {
+        let name = file.file_name()?.to_str()?;
+        [&res.stderr, &res.stdout].iter().find_map(|output| {
+            let output = String::from_utf8_lossy(output);
+            traceback(&output, name).or_else(|| file_line_col(&output, name))
+        })
+    }
+
+    pub(crate) fn editor_args(
+        editor: &str,
+        file: &str,
+        location: Option<&Location>,
+    ) -> Vec<String> {
+        let loc = if let Some(loc) = location {
+            loc
+        } else {
+            return vec![file.to_string()];
+        };
+        let (line, col) = (loc.line, loc.column.unwrap_or(1));
+        let name = Path::new(editor)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(editor);
+        match name {
+            "code" | "code-insiders" | "codium" | "vscodium" | "cursor" => {
+                vec!["--goto".to_string(), format!("{file}:{line}:{col}")]
+            }
+            "subl" | "hx" | "helix" | "zed" => vec![format!("{file}:{line}:{col}")],
+            "nano" => vec![format!("+{line},{col}"), file.to_string()],
+            "emacs" | "emacsclient" | "micro" => vec![format!("+{line}:{col}"), file.to_string()],
+            "vim" | "nvim" | "gvim" => {
+                vec![format!("+call cursor({line},{col})"), file.to_string()]
+            }
+            "vi" | "kak" | "gedit" | "joe" | "mg" | "ne" => {
+                vec![format!("+{line}"), file.to_string()]
+            }
+            _ => vec![file.to_string()],
+        }
+    }
+
+    pub(crate) fn load(path: &Path) -> Option<Location> {
+        let contents = fs::read_to_string(path).ok()?;
+        let mut it = contents.trim().split(':');
+        let line = it.next()?.parse().ok()?;
+        let column = it.next().and_then(|col| col.parse().ok());
+        Some(Location { line, column })
+    }
+
+    pub(crate) fn store(path: &Path, location: Option<&Location>) -> Error<()> {
+        let res = match location {
+            Some(Location {
+                line,
+                column: Some(col),
+            }) => fs::write(path, format!("{line}:{col}")),
+            Some(Location { line, column: None }) => fs::write(path, line.to_string()),
+            None => match fs::remove_file(path) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                res => res,
+            },
+        };
+        res.or_else(|e| {
+            dier!(
+                Codes::FileError,
+                "Could not update error location file '{}': {e}",
+                path.to_string_lossy()
+            )
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::os::unix::process::ExitStatusExt;
+
+        fn output(stdout: &str, stderr: &str) -> process::Output {
+            process::Output {
+                status: process::ExitStatus::from_raw(1 << 8),
+                stdout: stdout.as_bytes().to_vec(),
+                stderr: stderr.as_bytes().to_vec(),
+            }
+        }
+
+        fn location(stdout: &str, stderr: &str) -> Option<(usize, Option<usize>)> {
+            find(&output(stdout, stderr), Path::new("/tmp/hist/main.c"))
+                .map(|loc| (loc.line, loc.column))
+        }
+
+        #[test]
+        fn compiler() {
+            let err = "/tmp/hist/main.c: In function 'main':\n\
+                       /tmp/hist/main.c:4:5: error: expected ';' before '}' token\n";
+            assert_eq!(location("", err), Some((4, Some(5))));
+            assert_eq!(
+                location("", "main.c:12: undefined reference\n"),
+                Some((12, None))
+            );
+            assert_eq!(location("main.c:3:1: error\n", ""), Some((3, Some(1))));
+        }
+
+        #[test]
+        fn other_files() {
+            assert_eq!(location("", "/tmp/domain.c:4:5: error\n"), None);
+            assert_eq!(location("", "main.cpp:4:5: error\n"), None);
+            assert_eq!(location("", "in main.c: something\n"), None);
+            assert_eq!(
+                location("", "lib_main.c:1:1: error\nmain.c:7:2: error\n"),
+                Some((7, Some(2)))
+            );
+        }
+
+        #[test]
+        fn python() {
+            let err = "Traceback (most recent call last):\n\
+                       \x20 File \"/tmp/hist/main.py\", line 9, in <module>\n\
+                       \x20 File \"/tmp/hist/main.py\", line 3, in f\n\
+                       \x20 File \"/usr/lib/python3/json.py\", line 20, in loads\n\
+                       ValueError\n";
+            let loc = find(&output("", err), Path::new("/tmp/hist/main.py")).unwrap();
+            assert_eq!((loc.line, loc.column), (3, None));
+        }
+
+        #[test]
+        fn editors() {
+            let loc = Location {
+                line: 4,
+                column: Some(5),
+            };
+            let args = |editor| editor_args(editor, "main.c", Some(&loc));
+            assert_eq!(args("vim"), ["+call cursor(4,5)", "main.c"]);
+            assert_eq!(args("/usr/bin/nvim"), ["+call cursor(4,5)", "main.c"]);
+            assert_eq!(args("code"), ["--goto", "main.c:4:5"]);
+            assert_eq!(args("hx"), ["main.c:4:5"]);
+            assert_eq!(args("nano"), ["+4,5", "main.c"]);
+            assert_eq!(args("emacs"), ["+4:5", "main.c"]);
+            assert_eq!(args("vi"), ["+4", "main.c"]);
+            assert_eq!(args("ed"), ["main.c"]);
+            assert_eq!(editor_args("vim", "main.c", None), ["main.c"]);
+        }
+
+        #[test]
+        fn missing_column() {
+            let loc = Location {
+                line: 7,
+                column: None,
+            };
+            assert_eq!(
+                editor_args("code", "main.c", Some(&loc)),
+                ["--goto", "main.c:7:1"]
+            );
+        }
+    }
+}
+
+mod config {
+    use super::*;
+
+    const RUNNERS_DIR: &str = "runners";
+    const SNIPPETS_DIR: &str = "snippets";
+    const ALIASES_FILE: &str = "aliases";
+
+    fn leak(s: String) -> &'static str {
+        Box::leak(s.into_boxed_str())
+    }
+
+    // NOTE: Editors leave swap and backup files next to the ones being edited
+    fn is_ignored(path: &Path) -> bool {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        name.starts_with('.') || name.ends_with('~')
+    }
+
+    fn files(dir: &Path) -> Error<Vec<PathBuf>> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => dier!(
+                Codes::FileError,
+                "Could not read directory '{}': {e}",
+                dir.to_string_lossy()
+            ),
+        };
+        let mut files = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && !is_ignored(path))
+            .collect::<Vec<_>>();
+        files.sort();
+        Ok(files)
+    }
+
+    fn read(path: &Path) -> Error<String> {
+        fs::read_to_string(path).or_else(|e| {
+            dier!(
+                Codes::FileError,
+                "Could not read '{}': {e}",
+                path.to_string_lossy()
+            )
+        })
+    }
+
+    enum Value {
+        Str(String),
+        Int,
+        List(Vec<Value>),
+    }
+
+    // NOTE: The same syntax as the files in src/runners/
+    struct Parser<'a> {
+        it: std::iter::Peekable<std::str::Chars<'a>>,
+        line: usize,
+    }
+
+    impl<'a> Parser<'a> {
+        fn new(src: &'a str) -> Self {
+            Parser {
+                it: src.chars().peekable(),
+                line: 1,
+            }
+        }
+
+        fn bump(self: &mut Self) -> Option<char> {
+            let c = self.it.next();
+            if c == Some('\n') {
+                self.line += 1;
+            }
+            c
+        }
+
+        fn peek(self: &mut Self) -> Option<char> {
+            while let Some(&c) = self.it.peek() {
+                if c.is_whitespace() {
+                    self.bump();
+                } else if c == '/' && self.it.clone().nth(1) == Some('/') {
+                    while !matches!(self.bump(), Some('\n') | None) {}
+                } else {
+                    break;
+                }
+            }
+            self.it.peek().copied()
+        }
+
+        fn err<T>(self: &Self, msg: String) -> Result<T, String> {
+            Err(format!("line {}: {msg}", self.line))
+        }
+
+        fn expect(self: &mut Self, expected: char) -> Result<(), String> {
+            match self.peek() {
+                Some(c) if c == expected => {
+                    self.bump();
+                    Ok(())
+                }
+                Some(c) => self.err(format!("expected '{expected}', found '{c}'")),
+                None => self.err(format!("expected '{expected}', found end of file")),
+            }
+        }
+
+        fn ident(self: &mut Self) -> Result<String, String> {
+            let mut ident = String::new();
+            while let Some(c) = self.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                ident.push(c);
+                self.bump();
+            }
+            if ident.is_empty() {
+                self.err("expected an identifier".to_string())
+            } else {
+                Ok(ident)
+            }
+        }
+
+        fn string(self: &mut Self) -> Result<String, String> {
+            self.expect('"')?;
+            let mut s = String::new();
+            loop {
+                match self.bump() {
+                    Some('"') => return Ok(s),
+                    Some('\\') => match self.bump() {
+                        Some('n') => s.push('\n'),
+                        Some('t') => s.push('\t'),
+                        Some(c @ ('\\' | '"' | '\'')) => s.push(c),
+                        Some(c) => return self.err(format!("unknown escape sequence '\\{c}'")),
+                        None => return self.err("unterminated string".to_string()),
+                    },
+                    Some(c) => s.push(c),
+                    None => return self.err("unterminated string".to_string()),
+                }
+            }
+        }
+
+        fn value(self: &mut Self) -> Result<Value, String> {
+            match self.peek() {
+                Some('"') => Ok(Value::Str(self.string()?)),
+                Some('&') => {
+                    self.bump();
+                    self.expect('[')?;
+                    let mut list = Vec::new();
+                    while self.peek() != Some(']') {
+                        list.push(self.value()?);
+                        if self.peek() != Some(',') {
+                            break;
+                        }
+                        self.bump();
+                    }
+                    self.expect(']')?;
+                    Ok(Value::List(list))
+                }
+                Some(c) if c.is_ascii_digit() => {
+                    let mut num = String::new();
+                    while let Some(c) = self.it.peek().copied().filter(char::is_ascii_digit) {
+                        num.push(c);
+                        self.bump();
+                    }
+                    num.parse::<usize>()
+                        .map(|_| Value::Int)
+                        .or_else(|e| self.err(format!("invalid number '{num}': {e}")))
+                }
+                Some(c) => self.err(format!("unexpected '{c}'")),
+                None => self.err("unexpected end of file".to_string()),
+            }
+        }
+
+        fn runner(self: &mut Self) -> Result<Vec<(String, Value)>, String> {
+            let name = self.ident()?;
+            if name != "Runner" {
+                return self.err(format!("expected 'Runner', found '{name}'"));
+            }
+            self.expect('{')?;
+            let mut fields = Vec::new();
+            while self.peek() != Some('}') {
+                let field = self.ident()?;
+                self.expect(':')?;
+                fields.push((field, self.value()?));
+                if self.peek() != Some(',') {
+                    break;
+                }
+                self.bump();
+            }
+            self.expect('}')?;
+            if let Some(c) = self.peek() {
+                return self.err(format!("unexpected '{c}' after the end of the runner"));
+            }
+            Ok(fields)
+        }
+    }
+
+    fn to_str(value: Value) -> Result<&'static str, String> {
+        match value {
+            Value::Str(s) => Ok(leak(s)),
+            _ => Err("expected a string".to_string()),
+        }
+    }
+
+    fn to_list(value: Value) -> Result<&'static [&'static str], String> {
+        match value {
+            Value::List(list) => Ok(list
+                .into_iter()
+                .map(to_str)
+                .collect::<Result<Vec<_>, _>>()?
+                .leak()),
+            _ => Err("expected a list of strings".to_string()),
+        }
+    }
+
+    fn to_list2(value: Value) -> Result<&'static [&'static [&'static str]], String> {
+        match value {
+            Value::List(list) => Ok(list
+                .into_iter()
+                .map(to_list)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| "expected a list of lists of strings".to_string())?
+                .leak()),
+            _ => Err("expected a list of lists of strings".to_string()),
+        }
+    }
+
+    fn parse_runner(src: &str) -> Result<Runner, String> {
+        let mut runner = Runner {
+            name: "",
+            extension: "",
+            exe_idx: 0,
+            exe_deps: &[],
+            other_deps: &[],
+            supported_langs: &[],
+            default_for: &[],
+            setup: &[],
+            exe_args_pre: &[],
+            exe_args_post: &[],
+            teardown: &[],
+        };
+        let mut seen = Vec::new();
+        for (field, value) in Parser::new(src).runner()? {
+            if seen.contains(&field) {
+                return Err(format!("field '{field}' specified more than once"));
+            }
+            let res = match field.as_str() {
+                "name" => to_str(value).map(|v| runner.name = v),
+                "extension" => to_str(value).map(|v| runner.extension = v),
+                "exe_idx" => match value {
+                    Value::Int => Ok(()),
+                    _ => Err("expected a number".to_string()),
+                },
+                "exe_deps" => to_list(value).map(|v| runner.exe_deps = v),
+                "other_deps" => to_list2(value).map(|v| runner.other_deps = v),
+                "supported_langs" => to_list(value).map(|v| runner.supported_langs = v),
+                "default_for" => to_list(value).map(|v| runner.default_for = v),
+                "setup" => to_list2(value).map(|v| runner.setup = v),
+                "exe_args_pre" => to_list(value).map(|v| runner.exe_args_pre = v),
+                "exe_args_post" => to_list(value).map(|v| runner.exe_args_post = v),
+                "teardown" => to_list2(value).map(|v| runner.teardown = v),
+                _ => Err("unknown field".to_string()),
+            };
+            res.map_err(|e| format!("field '{field}': {e}"))?;
+            seen.push(field);
+        }
+        for field in ["name", "extension", "supported_langs"] {
+            if !seen.iter().any(|f| f == field) {
+                return Err(format!("missing field '{field}'"));
+            }
+        }
+        if runner.supported_langs.is_empty() {
+            return Err("field 'supported_langs': expected at least one language".to_string());
+        }
+        if runner.exe_deps.is_empty() && !runner.exe_args_pre.is_empty() {
+            return Err(
+                "field 'exe_args_pre': a runner without 'exe_deps' cannot have 'pre' args"
+                    .to_string(),
+            );
+        }
+        Ok(runner)
+    }
+
+    pub(crate) fn snippets(config_dir: &Path) -> Error<Snippets> {
+        let mut snippets = Snippets::new();
+        for path in files(&config_dir.join(CONFIG_DIR).join(SNIPPETS_DIR))? {
+            let lang = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| {
+                    dieo!(
+                        Codes::FileError,
+                        "Snippet file name '{}' is not valid UTF-8",
+                        path.to_string_lossy()
+                    )
+                })?
+                .to_string();
+            snippets.insert(leak(lang), leak(read(&path)?));
+        }
+        Ok(snippets)
+    }
+
+    pub(crate) fn aliases(config_dir: &Path, snippets: &Snippets) -> Error<Aliases> {
+        let path = config_dir.join(CONFIG_DIR).join(ALIASES_FILE);
+        if !path.exists() {
+            return Ok(Aliases::new());
+        }
+        let mut aliases = Aliases::new();
+        for (i, line) in read(&path)?.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (alias, lang) = line.split_once(':').ok_or_else(|| {
+                dieo!(
+                    Codes::LanguageError,
+                    "{}:{}: expected 'alias : language'",
+                    path.to_string_lossy(),
+                    i + 1
+                )
+            })?;
+            let (alias, lang) = (alias.trim(), lang.trim());
+            if snippets.contains_key(alias) {
+                dier!(
+                    Codes::LanguageError,
+                    "{}:{}: alias '{alias}' is the same as a language name",
+                    path.to_string_lossy(),
+                    i + 1
+                )
+            }
+            let lang = *snippets
+                .get_key_value(lang)
+                .ok_or_else(|| {
+                    dieo!(
+                        Codes::LanguageError,
+                        "{}:{}: alias '{alias}' refers to unknown language '{lang}'",
+                        path.to_string_lossy(),
+                        i + 1
+                    )
+                })?
+                .0;
+            if aliases.insert(leak(alias.to_string()), lang).is_some() {
+                dier!(
+                    Codes::LanguageError,
+                    "{}:{}: alias '{alias}' is already registered",
+                    path.to_string_lossy(),
+                    i + 1
+                )
+            }
+        }
+        Ok(aliases)
+    }
+
+    pub(crate) fn runners(config_dir: &Path) -> Error<Vec<Runner>> {
+        let mut runners: Vec<(Runner, PathBuf)> = Vec::new();
+        for path in files(&config_dir.join(CONFIG_DIR).join(RUNNERS_DIR))? {
+            let runner = parse_runner(&read(&path)?).or_else(|e| {
+                dier!(
+                    Codes::RunnerError,
+                    "Could not load runner '{}': {e}",
+                    path.to_string_lossy()
+                )
+            })?;
+            if let Some((_, other)) = runners.iter().find(|(r, _)| r.name == runner.name) {
+                dier!(
+                    Codes::RunnerError,
+                    "Runner '{}' is defined in both '{}' and '{}'",
+                    runner.name,
+                    other.to_string_lossy(),
+                    path.to_string_lossy()
+                )
+            }
+            runners.push((runner, path));
+        }
+        Ok(runners.into_iter().map(|(runner, _)| runner).collect())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::sync::atomic::AtomicUsize;
+        use std::sync::atomic::Ordering::SeqCst;
+
+        #[test]
+        fn builtin_runners() {
+            let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("src")
+                .join(RUNNERS_DIR);
+            for path in files(&dir).unwrap() {
+                let src = fs::read_to_string(&path).unwrap();
+                if let Err(e) = parse_runner(&src) {
+                    panic!("{}: {e}", path.to_string_lossy());
+                }
+            }
+        }
+
+        #[test]
+        fn runner() {
+            let runner = parse_runner(
+                r#"
+                // a comment
+                Runner {
+                    name: "r", // another comment
+                    extension: "x",
+                    exe_idx: 0,
+                    exe_deps: &["a", "b",],
+                    other_deps: &[&["c"], &[]],
+                    supported_langs: &["l"],
+                    setup: &[&["echo", "\"q\" \\ \n\t\'"]],
+                }
+                "#,
+            )
+            .unwrap();
+            assert_eq!((runner.name, runner.extension), ("r", "x"));
+            assert_eq!(runner.exe_deps, ["a", "b"]);
+            assert_eq!(runner.other_deps, [&["c"][..], &[]]);
+            assert_eq!(runner.supported_langs, ["l"]);
+            assert!(runner.default_for.is_empty() && runner.teardown.is_empty());
+            assert_eq!(runner.setup, [&["echo", "\"q\" \\ \n\t'"]]);
+        }
+
+        fn error(fields: &str) -> String {
+            parse_runner(&format!("Runner {{ {fields} }}"))
+                .err()
+                .unwrap()
+        }
+
+        #[test]
+        fn fields() {
+            let base = r#"name: "r", extension: "x", supported_langs: &["l"]"#;
+            assert_eq!(
+                error(&format!(r#"{base}, name: "s""#)),
+                "field 'name' specified more than once"
+            );
+            assert_eq!(
+                error(&format!(r#"{base}, colour: "red""#)),
+                "field 'colour': unknown field"
+            );
+            assert_eq!(
+                error(&format!(r#"{base}, setup: &["a"]"#)),
+                "field 'setup': expected a list of lists of strings"
+            );
+            assert_eq!(
+                error(r#"name: "r", supported_langs: &["l"]"#),
+                "missing field 'extension'"
+            );
+            assert_eq!(
+                error(r#"name: "r", extension: "x", supported_langs: &[]"#),
+                "field 'supported_langs': expected at least one language"
+            );
+            assert!(error(&format!(r#"{base}, exe_args_pre: &["-u"]"#)).contains("exe_args_pre"));
+        }
+
+        #[test]
+        fn syntax() {
+            assert_eq!(
+                parse_runner("Runner {\n name: \"r\"\n extension: \"x\" }").err(),
+                Some("line 3: expected '}', found 'e'".to_string())
+            );
+            assert_eq!(
+                parse_runner("Runner { name: \"a\\qb\" }").err(),
+                Some("line 1: unknown escape sequence '\\q'".to_string())
+            );
+            assert_eq!(
+                parse_runner("Runner { name: \"r").err(),
+                Some("line 1: unterminated string".to_string())
+            );
+            assert_eq!(
+                parse_runner("Runner {}\nRunner {}").err(),
+                Some("line 2: unexpected 'R' after the end of the runner".to_string())
+            );
+            assert_eq!(
+                parse_runner("Runer {}").err(),
+                Some("line 1: expected 'Runner', found 'Runer'".to_string())
+            );
+        }
+
+        fn aliases_from(contents: &str) -> Error<Aliases> {
+            static COUNT: AtomicUsize = AtomicUsize::new(0);
+            let dir = env::temp_dir().join(format!(
+                "ruc-test-aliases-{}-{}",
+                process::id(),
+                COUNT.fetch_add(1, SeqCst)
+            ));
+            fs::create_dir_all(dir.join(CONFIG_DIR)).unwrap();
+            fs::write(dir.join(CONFIG_DIR).join(ALIASES_FILE), contents).unwrap();
+            let snippets = Snippets::from([("c", ""), ("python", "")]);
+            let res = aliases(&dir, &snippets);
+            fs::remove_dir_all(&dir).unwrap();
+            res
+        }
+
+        #[test]
+        fn user_aliases() {
+            let aliases = aliases_from("py : python\n\n  h:c  \n").unwrap();
+            assert_eq!(aliases.len(), 2);
+            assert_eq!(aliases["py"], "python");
+            assert_eq!(aliases["h"], "c");
+        }
+
+        #[test]
+        fn alias_errors() {
+            assert_eq!(
+                aliases_from("py python\n").err(),
+                Some(Codes::LanguageError)
+            );
+            assert_eq!(
+                aliases_from("c : python\n").err(),
+                Some(Codes::LanguageError)
+            );
+            assert_eq!(
+                aliases_from("rs : rust\n").err(),
+                Some(Codes::LanguageError)
+            );
+            assert_eq!(
+                aliases_from("py : python\npy : c\n").err(),
+                Some(Codes::LanguageError)
+            );
+        }
+    }
+}
+
+struct Runners {
+    runners: Vec<Runner>,
+}
+
+impl Runners {
+    fn new(config_dir: &Path, snippets: &Snippets) -> Error<Self> {
+        let mut runners: Vec<Runner> = include!(concat!(env!("OUT_DIR"), "/runners_list"));
+        for user in config::runners(config_dir)?.into_iter().rev() {
+            for lang in user.supported_langs.iter().chain(user.default_for) {
+                if !snippets.contains_key(lang) {
+                    dier!(
+                        Codes::RunnerError,
+                        "User runner '{}' refers to unknown language '{lang}'",
+                        user.name
+                    )
+                }
+            }
+            if let Some(lang) = user
+                .default_for
+                .iter()
+                .find(|lang| !user.supported_langs.contains(lang))
+            {
+                dier!(
+                    Codes::RunnerError,
+                    "User runner '{}' cannot be the default for '{lang}' without supporting it",
+                    user.name
+                )
+            }
+            // NOTE: User runners go first, so that they take priority when picking a default
+            runners.retain(|r| r.name != user.name);
+            runners.insert(0, user);
+        }
+        Ok(Runners { runners })
+    }
+    fn get<'a>(self: &'a Self, name: &'a str, lang: &'a str) -> Option<Error<&'a Runner>> {
+        for r in &self.runners {
+            if r.name == name {
+                if r.supported_langs.contains(&lang) {
+                    return Some(Ok(r));
+                }
+            }
+        }
+        None
+    }
+
+    fn lang_for_extension(self: &Self, extension: &str) -> Option<&'static str> {
+        let mut it = self.runners.iter().filter(|r| r.extension == extension);
+        it.clone()
+            .find_map(|r| r.default_for.first())
+            .or_else(|| it.find_map(|r| r.supported_langs.first()))
+            .copied()
+    }
+
+    fn runner_for_lang<'a>(self: &Self, lang: &'a str) -> Error<&Runner> {
+        for r in &self.runners {
+            if r.default_for.contains(&lang) {
+                return Ok(r);
+            }
+        }
+        Err(Codes::RunnerError)
+    }
+
+    fn determine<'a>(self: &Self, user_runner: Option<&'a String>, lang: &'a str) -> Error<Runner> {
+        let runner = if let Some(runner) = user_runner {
+            if let Some(r) = self.get(runner.as_str(), lang) {
+                r.or_else(|c| {
+                    dier!(
+                        c,
+                        "Specified runner '{runner}' cannot be used with '{lang}'"
+                    )
+                })?
+            } else {
+                dier!(Codes::RunnerError, "Unsupported runner '{runner}'")
+            }
         } else {
-            self.runner_for_lang(lang)
-                .or_else(|c| dier!(c, "Could not find default runner for '{lang}'"))?
+            self.runner_for_lang(lang).or_else(|c| {
+                dier!(
+                    c,
+                    "Could not find default runner for '{lang}', add a runner which lists it in 'default_for' to 'runners/' in the config directory"
+                )
+            })?
         };
 
         let mut runner = (*runner).clone();
@@ -738,16 +3212,18 @@ impl Runners {
 
 include!(concat!(env!("OUT_DIR"), "/lang_macro"));
 
-include!(concat!(env!("OUT_DIR"), "/lang_list"));
-
 type Aliases = HashMap<&'static str, &'static str>;
-fn aliases() -> Aliases {
-    include!(concat!(env!("OUT_DIR"), "/alias_map"))
+fn aliases(config_dir: &Path, snippets: &Snippets) -> Error<Aliases> {
+    let mut aliases: Aliases = include!(concat!(env!("OUT_DIR"), "/alias_map"));
+    aliases.extend(config::aliases(config_dir, snippets)?);
+    Ok(aliases)
 }
 
 type Snippets = HashMap<&'static str, &'static str>;
-fn snippets() -> Snippets {
-    include!(concat!(env!("OUT_DIR"), "/snippet_map"))
+fn snippets(config_dir: &Path) -> Error<Snippets> {
+    let mut snippets: Snippets = include!(concat!(env!("OUT_DIR"), "/snippet_map"));
+    snippets.extend(config::snippets(config_dir)?);
+    Ok(snippets)
 }
 fn get_snippet<'a>(snippets: &'a Snippets, lang: &'a str) -> Error<&'a str> {
     Ok(*snippets.get(lang).ok_or_else(|| {
@@ -755,13 +3231,28 @@ fn get_snippet<'a>(snippets: &'a Snippets, lang: &'a str) -> Error<&'a str> {
     })?)
 }
 
-fn determine_lang<'a>(args: &'a Args, aliases: &'a Aliases) -> Error<&'a str> {
+fn determine_lang<'a>(
+    args: &'a Args,
+    snippets: &'a Snippets,
+    aliases: &'a Aliases,
+    runners: &Runners,
+) -> Error<&'a str> {
     if args.lang.is_empty() {
+        if let Some(file) = &args.file {
+            let extension = file.extension().and_then(|e| e.to_str()).unwrap_or("");
+            return runners.lang_for_extension(extension).ok_or_else(|| {
+                dieo!(
+                    Codes::LanguageError,
+                    "Could not infer the language of '{}', try specifying it explicitly",
+                    file.to_string_lossy()
+                )
+            });
+        }
         if args.list == List::None {
             dier!(Codes::InternalError, "Language is empty")
         }
         Ok(args.lang.as_str())
-    } else if LANGS.contains(&args.lang.as_str()) {
+    } else if snippets.contains_key(args.lang.as_str()) {
         Ok(args.lang.as_str())
     } else if let Some(alias) = aliases.get(args.lang.as_str()) {
         Ok(alias)
@@ -786,12 +3277,129 @@ impl ToErrorCode<process::Output> for io::Result<process::Output> {
     }
 }
 
+impl ToErrorCode<Exited> for io::Result<Exited> {
+    fn to_code(self: Self, exe: &str) -> Error<Exited> {
+        self.or_else(|e| match sandbox::failed_step(&e) {
+            Some(msg) => dier!(Codes::SandboxError, "{exe} could not be sandboxed: {msg}"),
+            None => dier!(Codes::EditorError, "{exe} could not be started: {e}"),
+        })
+    }
+}
+
 impl ToErrorCode<()> for io::Result<()> {
     fn to_code(self: Self, _: &str) -> Error<()> {
         self.or_else(|e| dier!(Codes::FileError, "Could not create a temporary file: {e}"))
     }
 }
 
+// NOTE: Reads `pipe` until the end, optionally passing it through to stderr
+fn tee(mut pipe: impl Read, show: bool) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut buf = [0; 4096];
+    loop {
+        let len = match pipe.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if show {
+            io::stderr().write_all(&buf[..len])?;
+        }
+        out.extend_from_slice(&buf[..len]);
+    }
+    Ok(out)
+}
+
+struct Exited {
+    output: process::Output,
+    usage: libc::rusage,
+    wall: Duration,
+    timed_out: bool,
+}
+
+fn exited(
+    child: process::Child,
+    started: Instant,
+    watchdog: Option<limits::Watchdog>,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+) -> io::Result<Exited> {
+    signals::wait_exited(&child)?;
+    let wall = started.elapsed();
+    let timed_out = watchdog.is_some_and(limits::Watchdog::finish);
+    let (status, usage) = signals::reap(&child)?;
+    Ok(Exited {
+        output: process::Output {
+            status,
+            stdout,
+            stderr,
+        },
+        usage,
+        wall,
+        timed_out,
+    })
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum Stream {
+    Inherit,
+    Capture,
+    Tee,
+}
+
+trait OutputTee {
+    fn output_tee(
+        self: &mut Self,
+        stdout: Stream,
+        stderr: Stream,
+        timeout: Option<Duration>,
+    ) -> io::Result<Exited>;
+}
+
+impl OutputTee for Command {
+    // NOTE: Like `output`, but teed streams are also passed through to stderr
+    fn output_tee(
+        self: &mut Self,
+        stdout: Stream,
+        stderr: Stream,
+        timeout: Option<Duration>,
+    ) -> io::Result<Exited> {
+        if stdout != Stream::Inherit {
+            self.stdout(Stdio::piped());
+        }
+        if stderr != Stream::Inherit {
+            self.stderr(Stdio::piped());
+        }
+        let started = Instant::now();
+        let mut child = signals::spawn(self)?;
+        let watchdog = timeout.map(|timeout| limits::Watchdog::start(child.id(), timeout));
+        let stdout_thread = child
+            .stdout
+            .take()
+            .map(|pipe| thread::spawn(move || tee(pipe, stdout == Stream::Tee)));
+        let stderr = child
+            .stderr
+            .take()
+            .map(|pipe| tee(pipe, stderr == Stream::Tee))
+            .transpose()?;
+        let stdout = stdout_thread
+            .map(|thread| {
+                thread
+                    .join()
+                    .map_err(|_| io::Error::other("could not read stdout"))?
+            })
+            .transpose()?;
+        exited(
+            child,
+            started,
+            watchdog,
+            stdout.unwrap_or_default(),
+            stderr.unwrap_or_default(),
+        )
+    }
+}
+
 trait ToStrOrDie {
     fn to_str_or_die(self: &Self) -> Error<&str>;
 }
@@ -804,6 +3412,9 @@ impl ToStrOrDie for Path {
 }
 
 const CACHE_DIR: &str = "ruc_cache";
+const BUILD_DIR: &str = "builds";
+const CONFIG_DIR: &str = "ruc";
+const LOCATION_EXTENSION: &str = "location";
 
 fn add_prefix(lang: &str, runner: &str) -> String {
     let mut out = String::from(lang);
@@ -831,17 +3442,34 @@ fn cache_file_path(cache: &PathBuf, lang: &str, runner: &str, extension: &str) -
     Ok(cache_file_name(&cache_dir, lang, runner, extension))
 }
 
+const CURSOR: &str = "%CURSOR%";
+
+fn strip_cursor(snippet: &str) -> (String, Option<diagnostics::Location>) {
+    if let Some(idx) = snippet.find(CURSOR) {
+        let before = &snippet[..idx];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let location = diagnostics::Location {
+            line: before.matches('\n').count() + 1,
+            column: Some(before[line_start..].chars().count() + 1),
+        };
+        (snippet.replace(CURSOR, ""), Some(location))
+    } else {
+        (snippet.to_string(), None)
+    }
+}
+
 fn setup_hist<'a>(
     hist: Hist,
     cache_dir: &PathBuf,
+    scratch: &ScratchDir,
     lang: &'a str,
     runner: &str,
     extension: &str,
     snippet: &'a str,
-) -> Error<PathBuf> {
+) -> Error<(PathBuf, Option<diagnostics::Location>)> {
     let (mut file, path) = match hist {
         Hist::Temp => {
-            let path = cache_file_name(&env::temp_dir(), lang, runner, extension);
+            let path = cache_file_name(&scratch.path, lang, runner, extension);
             let file = fs::File::create(&path).to_code(&path.to_string_lossy())?;
             (file, path)
         }
@@ -849,7 +3477,7 @@ fn setup_hist<'a>(
             let cache_path = cache_file_path(cache_dir, lang, runner, extension)?;
             if hist == Hist::Use && cache_path.exists() {
                 return if cache_path.is_file() {
-                    Ok(cache_path)
+                    Ok((cache_path, None))
                 } else {
                     dier!(
                         Codes::FileError,
@@ -864,30 +3492,134 @@ fn setup_hist<'a>(
         }
     };
 
+    let (snippet, cursor) = strip_cursor(snippet);
     writeln!(file, "{}", snippet)
         .to_code(format!("Could not write to file '{}'", path.to_string_lossy()).as_str())?;
-    return Ok(path);
+    return Ok((path, cursor));
 }
 
-fn cleanup_temp(hist: &Hist, hist_path: &PathBuf) -> Error<()> {
-    if matches!(hist, Hist::Temp) {
-        fs::remove_file(hist_path).or_else(|e| {
+fn existing_hist(cache_dir: &PathBuf, lang: &str, runner: &str, extension: &str) -> Error<PathBuf> {
+    let cache_path = cache_file_path(cache_dir, lang, runner, extension)?;
+    if !cache_path.is_file() {
+        dier!(
+            Codes::FileError,
+            "Cannot rerun '{lang}' with '{runner}'. History file '{}' does not exist",
+            cache_path.to_string_lossy()
+        )
+    }
+    Ok(cache_path)
+}
+
+// NOTE: Only a pipe or a file counts, so that e.g. /dev/null (as in CI) still leads to the editor
+fn code_on_stdin() -> bool {
+    io::stdin()
+        .as_fd()
+        .try_clone_to_owned()
+        .map(fs::File::from)
+        .and_then(|stdin| stdin.metadata())
+        .is_ok_and(|meta| meta.file_type().is_fifo() || meta.is_file())
+}
+
+fn setup_stdin(scratch: &ScratchDir, lang: &str, runner: &str, extension: &str) -> Error<PathBuf> {
+    let path = cache_file_name(&scratch.path, lang, runner, extension);
+    let mut file = fs::File::create(&path).to_code(&path.to_string_lossy())?;
+    // NOTE: `io::copy` retries when interrupted, which would ignore Ctrl-C
+    let since = signals::received();
+    let mut stdin = io::stdin().lock();
+    let mut buf = [0; 8192];
+    loop {
+        let len = match stdin.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                signals::check(since)?;
+                continue;
+            }
+            Err(e) => dier!(Codes::FileError, "Could not read standard input: {e}"),
+        };
+        file.write_all(&buf[..len]).or_else(|e| {
             dier!(
                 Codes::FileError,
-                "Could not remove temporary file {}: {e}",
-                hist_path.to_string_lossy()
+                "Could not write standard input to '{}': {e}",
+                path.to_string_lossy()
             )
         })?;
     }
-    Ok(())
+    signals::check(since)?;
+    Ok(path)
+}
+
+// NOTE: mkdtemp gives it a random name and 0700 permissions
+struct ScratchDir {
+    path: PathBuf,
+}
+
+#[derive(Clone, Copy)]
+struct Context<'a> {
+    args: &'a Args,
+    lang: &'a str,
+    scratch: &'a ScratchDir,
+    report: &'a report::Report,
+}
+
+impl ScratchDir {
+    fn new() -> Error<Self> {
+        let template = env::temp_dir().join("ruc.XXXXXX");
+        let mut template = CString::new(template.into_os_string().into_vec())
+            .or_else(|e| dier!(Codes::FileError, "Invalid temporary directory: {e}"))?
+            .into_bytes_with_nul();
+        if unsafe { libc::mkdtemp(template.as_mut_ptr().cast()) }.is_null() {
+            dier!(
+                Codes::FileError,
+                "Could not create a temporary directory: {}",
+                io::Error::last_os_error()
+            )
+        }
+        template.pop();
+        Ok(ScratchDir {
+            path: OsString::from_vec(template).into(),
+        })
+    }
+
+    // NOTE: The only writable location inside of the sandbox
+    fn work_dir(self: &Self) -> PathBuf {
+        self.path.join("work")
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(self: &mut Self) {
+        if let Err(e) = fs::remove_dir_all(&self.path) {
+            println!(
+                "Could not remove temporary directory '{}': {e}",
+                self.path.to_string_lossy()
+            );
+        }
+    }
+}
+
+struct Editor {
+    exe: String,
+    args: Vec<String>,
+}
+
+impl Editor {
+    fn command(self: &Self, file: &str, location: Option<&diagnostics::Location>) -> Command {
+        let mut cmd = Command::new(&self.exe);
+        cmd.args(&self.args)
+            .args(diagnostics::editor_args(&self.exe, file, location));
+        cmd
+    }
 }
 
-fn editor(user_editor: &String) -> Error<String> {
+fn editor(user_editor: &String) -> Error<Editor> {
     let editor = if user_editor.is_empty() {
-        env::var_os("EDITOR")
+        env::var_os("VISUAL")
+            .filter(|v| !v.is_empty())
+            .or_else(|| env::var_os("EDITOR").filter(|v| !v.is_empty()))
             .ok_or_else(|| dieo!(
                     Codes::EditorError,
-                    "Could not determine which editor to use, try setting the EDITOR environment variable or using the -e flag."
+                    "Could not determine which editor to use, try setting the VISUAL or EDITOR environment variable or using the -e flag."
                     )
                 )?
             .to_string_lossy()
@@ -896,52 +3628,447 @@ fn editor(user_editor: &String) -> Error<String> {
         user_editor.clone()
     };
 
-    find_exe(&editor)
-        .ok_or_else(|| dieo!(Codes::EditorError, "Editor '{editor}' is not in PATH",))?;
+    let mut cmd = shell::split(&editor)
+        .or_else(|e| dier!(Codes::EditorError, "Could not parse editor '{editor}': {e}"))?
+        .into_iter();
+    let exe = cmd
+        .next()
+        .ok_or_else(|| dieo!(Codes::EditorError, "Editor command is empty"))?;
 
-    Ok(editor)
+    find_exe(&exe).ok_or_else(|| dieo!(Codes::EditorError, "Editor '{exe}' is not in PATH",))?;
+
+    Ok(Editor {
+        exe,
+        args: cmd.collect(),
+    })
 }
 
-fn run_editor(editor: &str, file: &str) -> Error<()> {
-    let res = Command::new(editor)
-        .arg(file)
+fn run_editor(editor: &Editor, file: &str, location: Option<&diagnostics::Location>) -> Error<()> {
+    let since = signals::received();
+    let res = editor
+        .command(file, location)
         .stdin(Stdio::inherit())
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit())
-        .output()
-        .to_code(editor)?;
+        .output_tee(Stream::Inherit, Stream::Inherit, None)
+        .to_code(&editor.exe)?;
+    signals::check(since)?;
 
-    check_status(&[editor], &res, Codes::EditorError)?;
+    check_status(&[&editor.exe], &res.output, Codes::EditorError)?;
     println!("Editor exited successfully\n{}\n", HLINE);
     Ok(())
 }
 
-fn program(args: Args) -> Error<()> {
-    let aliases = aliases();
-    let runners = Runners::new();
-    let snippets = snippets();
-    let lang = determine_lang(&args, &aliases)?;
+fn prompt(msg: &str) -> Error<Option<String>> {
+    print!("{msg}");
+    io::stdout()
+        .flush()
+        .or_else(|e| dier!(Codes::InternalError, "Could not flush stdout: {e}"))?;
+    // NOTE: `read_line` retries when interrupted, which would ignore Ctrl-C
+    let since = signals::received();
+    let mut stdin = io::stdin().lock();
+    let mut line = Vec::new();
+    loop {
+        let buf = match stdin.fill_buf() {
+            Ok(buf) => buf,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                signals::check(since)?;
+                continue;
+            }
+            Err(e) => dier!(Codes::FileError, "Could not read from standard input: {e}"),
+        };
+        if buf.is_empty() {
+            break;
+        }
+        let (len, done) = match buf.iter().position(|&c| c == b'\n') {
+            Some(idx) => (idx + 1, true),
+            None => (buf.len(), false),
+        };
+        line.extend_from_slice(&buf[..len]);
+        stdin.consume(len);
+        if done {
+            break;
+        }
+    }
+    if line.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(String::from_utf8_lossy(&line).trim().to_string()))
+    }
+}
+
+fn prompt_args(name: &str, current: Vec<String>) -> Error<Vec<String>> {
+    match prompt(&format!("{name} [{}]: ", shell::join(&current)))?.as_deref() {
+        None | Some("") => Ok(current),
+        Some("-") => Ok(Vec::new()),
+        Some(line) => shell::split(line).or_else(|e| {
+            println!("Could not parse arguments: {e}. Keeping the current arguments");
+            Ok(current)
+        }),
+    }
+}
+
+fn edit_loop(
+    editor: &Editor,
+    file: &Path,
+    mut compiler_args: Vec<String>,
+    mut prog_args: Vec<String>,
+    location: &mut Option<diagnostics::Location>,
+    run: impl Fn(&[String], &[String], &mut Option<diagnostics::Location>) -> Error<process::ExitStatus>,
+) -> Error<process::ExitStatus> {
+    let mut res = run(&compiler_args, &prog_args, location);
+    loop {
+        if res == Err(Codes::Interrupted) {
+            return res;
+        }
+        println!("\n{}", HLINE);
+        match prompt("[e]dit, [r]erun, change [a]rgs, [q]uit: ")?.as_deref() {
+            Some("e") => {
+                if run_editor(editor, &file.to_string_lossy(), location.as_ref()).is_err() {
+                    continue;
+                }
+            }
+            Some("r") => println!("{}\n", HLINE),
+            Some("a") => {
+                println!("Leave empty to keep the current arguments, use '-' to clear them");
+                compiler_args = prompt_args("Compiler arguments", compiler_args)?;
+                prog_args = prompt_args("Program arguments", prog_args)?;
+                println!("{}\n", HLINE);
+            }
+            Some("q") | None => return res,
+            Some(other) => {
+                println!("Unknown option '{other}'");
+                continue;
+            }
+        }
+        res = run(&compiler_args, &prog_args, location);
+    }
+}
+
+const WATCH_INTERVAL: Duration = Duration::from_millis(200);
+
+fn watch_editor(
+    editor: &Editor,
+    file: &Path,
+    mut run: impl FnMut() -> Error<process::ExitStatus>,
+) -> Error<()> {
+    let exe = &editor.exe;
+    // NOTE: The editor stays in ruc's process group, so that ruc and the runs keep the terminal
+    let child = editor
+        .command(&file.to_string_lossy(), None)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .or_else(|e| dier!(Codes::EditorError, "{exe} could not be started: {e}"))?;
+    println!(
+        "Watching '{}' for changes\n{}\n",
+        file.to_string_lossy(),
+        HLINE
+    );
+
+    let since = signals::received();
+    let res = watch_loop(&child, file, since, &mut run);
+    if !matches!(res, Ok(true)) {
+        unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) };
+    }
+    let (status, _) = signals::reap(&child)
+        .or_else(|e| dier!(Codes::EditorError, "Could not wait for {exe}: {e}"))?;
+    res?;
+    signals::check(since)?;
+    let res = process::Output {
+        status,
+        stdout: Vec::new(),
+        stderr: Vec::new(),
+    };
+    check_status(&[exe], &res, Codes::EditorError)?;
+    println!("Editor exited successfully");
+    Ok(())
+}
+
+// NOTE: Returns whether the editor has exited, without reaping it
+fn watch_loop(
+    child: &process::Child,
+    file: &Path,
+    since: usize,
+    run: &mut impl FnMut() -> Error<process::ExitStatus>,
+) -> Error<bool> {
+    let modified = || fs::metadata(file).and_then(|m| m.modified()).ok();
+    let mut last = modified();
+    loop {
+        thread::sleep(WATCH_INTERVAL);
+        signals::check(since)?;
+        let exited = signals::has_exited(child)
+            .or_else(|e| dier!(Codes::EditorError, "Could not wait for the editor: {e}"))?;
+        let mut current = modified();
+        if current != last {
+            // NOTE: Some editors write files in several steps, wait for the writes to settle.
+            while {
+                last = current;
+                thread::sleep(WATCH_INTERVAL);
+                signals::check(since)?;
+                current = modified();
+                current != last
+            } {}
+            println!("File saved\n{}\n", HLINE);
+            if run() == Err(Codes::Interrupted) {
+                return Err(Codes::Interrupted);
+            }
+            println!("\n{}\n", HLINE);
+        }
+        if exited {
+            return Ok(true);
+        }
+    }
+}
+
+fn program(mut args: Args, report: &report::Report) -> Error<process::ExitStatus> {
+    let snippets = snippets(&args.config_dir)?;
+    let aliases = aliases(&args.config_dir, &snippets)?;
+    let runners = Runners::new(&args.config_dir, &snippets)?;
+    if args.file.is_none()
+        && !snippets.contains_key(args.lang.as_str())
+        && !aliases.contains_key(args.lang.as_str())
+        && Path::new(&args.lang).is_file()
+    {
+        args.file = Some(mem::take(&mut args.lang).into());
+    }
+    let lang = determine_lang(&args, &snippets, &aliases, &runners)?;
 
-    list(args.list, lang, &aliases, &runners)?;
+    list(args.list, lang, &snippets, &aliases, &runners)?;
 
     let runner = runners.determine(args.runner.as_ref(), lang)?;
-    let snippet = get_snippet(&snippets, lang)?;
-    let hist_path = setup_hist(
-        args.hist,
-        &args.cache_dir,
+    let scratch = ScratchDir::new()?;
+    let ctx = Context {
+        args: &args,
         lang,
-        runner.name,
-        runner.extension,
-        snippet,
-    )?;
-    let editor = editor(&args.editor)?;
-
-    run_editor(&editor, &hist_path.as_os_str().to_string_lossy())?;
-    let run_res = runner.run(&lang, &hist_path, &args.compiler_args, &args.prog_args);
-    cleanup_temp(&args.hist, &hist_path)?;
+        scratch: &scratch,
+        report,
+    };
+    let run_with = |file: &Path,
+                    compiler_args: &[String],
+                    prog_args: &[String],
+                    location: &mut Option<diagnostics::Location>| {
+        let res = runner.run(&ctx, file, compiler_args, prog_args, location);
+        if args.time {
+            report.print_times();
+        }
+        res
+    };
+    let run = |file: &Path, location: &mut Option<diagnostics::Location>| {
+        run_with(file, &args.compiler_args, &args.prog_args, location)
+    };
+    if let Some(file) = &args.file {
+        if !file.is_file() {
+            dier!(
+                Codes::FileError,
+                "Cannot run '{}'. Path does not exist or is not a regular file",
+                file.to_string_lossy()
+            )
+        }
+        return run(file, &mut None);
+    }
+    if !args.rerun && code_on_stdin() {
+        if args.interactive || args.watch {
+            dier!(
+                Codes::ArgumentError,
+                "--interactive and --watch cannot be used when the code is piped to ruc"
+            )
+        }
+        let code_path = setup_stdin(&scratch, lang, runner.name, runner.extension)?;
+        return run(&code_path, &mut None);
+    }
+    let (hist_path, cursor) = if args.rerun {
+        (
+            existing_hist(&args.cache_dir, lang, runner.name, runner.extension)?,
+            None,
+        )
+    } else {
+        setup_hist(
+            args.hist,
+            &args.cache_dir,
+            &scratch,
+            lang,
+            runner.name,
+            runner.extension,
+            get_snippet(&snippets, lang)?,
+        )?
+    };
+    let location_path = if args.hist == Hist::Temp {
+        None
+    } else {
+        Some(cache_file_path(
+            &args.cache_dir,
+            lang,
+            runner.name,
+            LOCATION_EXTENSION,
+        )?)
+    };
+    let mut location = cursor.or_else(|| {
+        location_path
+            .as_ref()
+            .filter(|_| args.hist == Hist::Use)
+            .and_then(|path| diagnostics::load(path))
+    });
+
+    let run_res = if args.rerun && !args.interactive {
+        run(&hist_path, &mut location)
+    } else {
+        let editor = editor(&args.editor)?;
+        if args.watch {
+            watch_editor(&editor, &hist_path, || run(&hist_path, &mut location))
+                .map(|_| process::ExitStatus::default())
+        } else {
+            if !args.rerun {
+                run_editor(
+                    &editor,
+                    &hist_path.as_os_str().to_string_lossy(),
+                    location.as_ref(),
+                )?;
+            }
+            if args.interactive {
+                edit_loop(
+                    &editor,
+                    &hist_path,
+                    args.compiler_args.clone(),
+                    args.prog_args.clone(),
+                    &mut location,
+                    |compiler_args, prog_args, location| {
+                        run_with(&hist_path, compiler_args, prog_args, location)
+                    },
+                )
+            } else {
+                run(&hist_path, &mut location)
+            }
+        }
+    };
+    if let Some(path) = location_path {
+        diagnostics::store(&path, location.as_ref())?;
+    }
     run_res
 }
 
 fn main() {
-    exit(program(parse_args()).map_or_else(identity, |_| Codes::Ok))
+    signals::install();
+    let args = parse_args();
+    let exit_status = args.exit_status;
+    let json = (args.format == Format::Json).then(|| {
+        report::redirect_stdout()
+            .unwrap_or_else(|e| die!(Codes::InternalError, "Could not redirect stdout: {e}"))
+    });
+    let report = report::Report::default();
+    let res = program(args, &report);
+    if let Some(out) = json {
+        report
+            .write_json(out, &res)
+            .unwrap_or_else(|e| die!(Codes::InternalError, "Could not write the report: {e}"));
+    }
+    match res {
+        Ok(status) if exit_status => process::exit(
+            status
+                .code()
+                .or_else(|| status.signal().map(|sig| 128 + sig))
+                .unwrap_or(0),
+        ),
+        Ok(_) => exit(Codes::Ok),
+        Err(code) => exit(code),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor() {
+        let (snippet, loc) = strip_cursor("int main() {\n    %CURSOR%\n}\n");
+        assert_eq!(snippet, "int main() {\n    \n}\n");
+        let loc = loc.unwrap();
+        assert_eq!((loc.line, loc.column), (2, Some(5)));
+
+        let (_, loc) = strip_cursor("%CURSOR%print()");
+        let loc = loc.unwrap();
+        assert_eq!((loc.line, loc.column), (1, Some(1)));
+
+        let (_, loc) = strip_cursor("// é\n  é %CURSOR%");
+        let loc = loc.unwrap();
+        assert_eq!((loc.line, loc.column), (2, Some(5)));
+    }
+
+    #[test]
+    fn no_cursor() {
+        let (snippet, loc) = strip_cursor("print()\n");
+        assert_eq!(snippet, "print()\n");
+        assert!(loc.is_none());
+    }
+
+    fn runner(
+        setup: &'static [&'static [&'static str]],
+        teardown: &'static [&'static [&'static str]],
+    ) -> Runner {
+        Runner {
+            name: "test",
+            extension: "c",
+            exe_idx: Runner::NATIVE,
+            exe_deps: &[],
+            other_deps: &[],
+            supported_langs: &["c"],
+            default_for: &[],
+            setup,
+            exe_args_pre: &[],
+            exe_args_post: &[],
+            teardown,
+        }
+    }
+
+    #[test]
+    fn out_suffix() {
+        let out = runner(
+            &[&["cc", "-o", "%OUTPUT_FILE%"]],
+            &[&["rm", "%OUTPUT_FILE%"]],
+        );
+        assert_eq!(out.out_suffix(), Ok(Some("")));
+        let jar = runner(&[&["kotlinc", "-d", "%OUTPUT_FILE:.jar%"]], &[]);
+        assert_eq!(jar.out_suffix(), Ok(Some(".jar")));
+        let none = runner(&[], &[&["rm", "-f", "%WORK_DIR%/a.out"]]);
+        assert_eq!(none.out_suffix(), Ok(None));
+        let mixed = runner(
+            &[&["cc", "-o", "%OUTPUT_FILE%"]],
+            &[&["rm", "%OUTPUT_FILE:.o%"]],
+        );
+        assert_eq!(mixed.out_suffix(), Err(Codes::RunnerError));
+    }
+
+    #[test]
+    fn stable_hash() {
+        let hash = |parts: &[&str]| {
+            let mut hasher = StableHasher::new();
+            for part in parts {
+                hasher.add(part.as_bytes());
+            }
+            hasher.0
+        };
+        assert_eq!(hash(&["hello"]), 0xff7a61ff11320f78);
+        assert_eq!(hash(&["a", "b"]), hash(&["a", "b"]));
+        assert_ne!(hash(&["ab", "c"]), hash(&["a", "bc"]));
+        assert_ne!(hash(&["a", ""]), hash(&["a"]));
+    }
+
+    #[test]
+    fn build_key() {
+        let dir = env::temp_dir().join(format!("ruc-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("main.c");
+        let out = runner(&[&["cc", "-o", "%OUTPUT_FILE%"]], &[]);
+        let key = |contents: &str, args: &[&str]| {
+            fs::write(&file, contents).unwrap();
+            let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+            out.build_key(&file, &[], &args).unwrap()
+        };
+        let base = key("int main;", &[]);
+        assert_eq!(key("int main;", &[]), base);
+        assert_ne!(key("int main ;", &[]), base);
+        assert_ne!(key("int main;", &["-O2"]), base);
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }